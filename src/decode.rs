@@ -0,0 +1,74 @@
+//! Decoding of interactive mode inputs given in a text encoding of bytes.
+
+use crate::error::DiffError;
+use base64::Engine;
+use clap::ValueEnum;
+use std::fmt::Write;
+
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Decoding {
+    /// Compare the inputs as given
+    #[default]
+    None,
+    /// Standard base64, with padding
+    Base64,
+    /// Two hex digits per byte, in either case
+    Hex,
+}
+
+/// Number of bytes per line of a hex dump.
+const DUMP_WIDTH: usize = 16;
+
+impl Decoding {
+    /// The bytes encoded in `text`, ignoring whitespace around encoded ones.
+    pub fn decode(self, text: &str, side: &str) -> Result<Vec<u8>, DiffError> {
+        match self {
+            Self::None => Ok(text.as_bytes().to_vec()),
+            Self::Base64 => base64::engine::general_purpose::STANDARD
+                .decode(text.trim())
+                .map_err(DiffError::parse(format!(
+                    "{side} input is not valid base64"
+                ))),
+            Self::Hex => decode_hex(text.trim()).ok_or_else(|| DiffError::ParseError {
+                context: format!("{side} input is not valid hex"),
+                source: "expected pairs of hex digits".into(),
+            }),
+        }
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    let digits = text.as_bytes();
+    if !digits.len().is_multiple_of(2) || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Formats bytes like `xxd`: an offset, the bytes in hex and their printable
+/// ASCII characters, `DUMP_WIDTH` bytes per line.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(DUMP_WIDTH).enumerate() {
+        let _ = write!(dump, "{:08x}:", line * DUMP_WIDTH);
+        for byte in chunk {
+            let _ = write!(dump, " {byte:02x}");
+        }
+        let padding = 3 * (DUMP_WIDTH - chunk.len());
+        let ascii = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        let _ = writeln!(dump, "{:padding$}  {ascii}", "");
+    }
+    dump
+}
@@ -0,0 +1,588 @@
+//! Recursive comparison of two directory trees.
+
+use crate::{
+    DiffOptions, DiffSummary, DiffVec, ReadOptions, STYLE_DELETED, STYLE_INSERTED, compute_diff,
+    read_file_with_encoding,
+};
+use crate::{error::DiffError, normalize::Normalization, parallel::parallel_map};
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const STAT_WIDTH: usize = 40;
+
+/// Inserted and deleted line counts of a single file.
+pub struct FileStat {
+    path: PathBuf,
+    insertions: usize,
+    deletions: usize,
+    pub summary: DiffSummary,
+}
+
+impl FileStat {
+    pub fn changed(&self) -> bool {
+        self.insertions + self.deletions > 0
+    }
+
+    pub fn new(path: PathBuf, diff: &DiffVec) -> Self {
+        let (insertions, deletions) = diff.line_changes();
+        Self {
+            path,
+            insertions,
+            deletions,
+            summary: diff.summary(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    /// A symlink that is compared by its target path rather than followed
+    Symlink,
+}
+
+/// Collects the paths of all files below `root`, relative to it.
+///
+/// Symlinks are recorded as entries of their own unless `follow_symlinks` is
+/// set, in which case they are resolved and directories already visited
+/// through another path are skipped to avoid cycles.
+fn walk(root: &Path, options: &DirOptions) -> Result<BTreeMap<PathBuf, EntryKind>> {
+    let follow_symlinks = options.follow_symlinks;
+    let mut files = BTreeMap::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if follow_symlinks && !visited.insert(std::fs::canonicalize(&dir)?) {
+            continue;
+        }
+        let entries = std::fs::read_dir(&dir)
+            .context(format!("Failed to read directory: {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry.context(format!("Failed to read directory: {}", dir.display()))?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            let is_dir = if file_type.is_symlink() && follow_symlinks {
+                path.is_dir()
+            } else {
+                file_type.is_dir()
+            };
+            let relative = path.strip_prefix(root)?.to_path_buf();
+            if options.filter.excludes(&relative) {
+                continue;
+            }
+            if is_dir {
+                pending.push(path);
+                continue;
+            }
+            if !options.filter.includes(&relative) {
+                continue;
+            }
+            let kind = if file_type.is_symlink() && !follow_symlinks {
+                EntryKind::Symlink
+            } else {
+                EntryKind::File
+            };
+            files.insert(relative, kind);
+        }
+    }
+    Ok(files)
+}
+
+/// Include and exclude globs matched against paths relative to the compared
+/// directories.
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let build = |patterns: &[String]| {
+            patterns
+                .iter()
+                .try_fold(GlobSetBuilder::new(), |mut builder, pattern| {
+                    builder.add(Glob::new(pattern).context(format!("Invalid glob: {pattern}"))?);
+                    anyhow::Ok(builder)
+                })?
+                .build()
+                .context("Failed to compile globs")
+        };
+        Ok(Self {
+            include: (!include.is_empty()).then(|| build(include)).transpose()?,
+            exclude: build(exclude)?,
+        })
+    }
+
+    /// Whether the path, or its file name alone, matches any set of globs.
+    fn matches(set: &GlobSet, path: &Path) -> bool {
+        set.is_match(path) || path.file_name().is_some_and(|name| set.is_match(name))
+    }
+
+    /// Whether a file or directory is skipped entirely.
+    fn excludes(&self, path: &Path) -> bool {
+        Self::matches(&self.exclude, path)
+    }
+
+    /// Whether a file is considered, which is any file unless include globs
+    /// are given.
+    fn includes(&self, path: &Path) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|include| Self::matches(include, path))
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
+pub struct DirOptions {
+    /// Summarize changed lines per file instead of printing the diffs
+    pub stat: bool,
+    /// Number of files compared in parallel
+    pub jobs: usize,
+    /// Compare the files symlinks point to instead of their target paths
+    pub follow_symlinks: bool,
+    /// Fail on the first file that cannot be compared instead of reporting it
+    /// and going on
+    pub strict: bool,
+    /// List the paths of changed files instead of printing the diffs
+    pub name_only: bool,
+    /// End the paths listed by `name_only` with NUL instead of a line break
+    pub null_data: bool,
+    pub filter: PathFilter,
+    pub read: ReadOptions,
+    pub normalization: Normalization,
+    pub diff: DiffOptions,
+}
+
+/// The comparison result of one file present in either tree.
+enum FileChange {
+    /// Both sides are equal, with the summary accounting for their size
+    Unchanged(DiffSummary),
+    Rendered(String),
+    Stat(FileStat),
+}
+
+/// Reads a file, or the target path of a symlink, as text.
+fn read_entry(
+    root: &Path,
+    path: &Path,
+    kind: Option<EntryKind>,
+    options: ReadOptions,
+) -> Result<String, DiffError> {
+    let path = root.join(path);
+    match kind {
+        Some(EntryKind::File) => read_file_with_encoding(path, options),
+        Some(EntryKind::Symlink) => std::fs::read_link(&path)
+            .map(|target| target.display().to_string())
+            .map_err(DiffError::io(format!(
+                "Failed to read symlink: {}",
+                path.display()
+            ))),
+        None => Ok(String::new()),
+    }
+}
+
+fn diff_file(
+    left: &Path,
+    right: &Path,
+    path: &Path,
+    kinds: (Option<EntryKind>, Option<EntryKind>),
+    options: &DirOptions,
+) -> Result<FileChange> {
+    let normalize = |text: String| options.normalization.apply(&text);
+    let left_text = normalize(read_entry(left, path, kinds.0, options.read)?);
+    let right_text = normalize(read_entry(right, path, kinds.1, options.read)?);
+    if kinds.0 == kinds.1 && left_text == right_text {
+        return Ok(FileChange::Unchanged(DiffSummary::unchanged(&left_text)));
+    }
+
+    Ok(match kinds {
+        _ if options.name_only => {
+            let status = match kinds {
+                (_, None) => 'D',
+                (None, _) => 'A',
+                _ => 'M',
+            };
+            FileChange::Rendered(format!("{status}\t{}", path.display()))
+        }
+        _ if options.stat => {
+            let diff = options.diff.line_diff(&left_text, &right_text)?;
+            FileChange::Stat(FileStat::new(path.to_path_buf(), &diff))
+        }
+        (_, None) => FileChange::Rendered(format!(
+            "{STYLE_DELETED}Only in {}: {}{STYLE_DELETED:#}",
+            left.display(),
+            path.display()
+        )),
+        (None, _) => FileChange::Rendered(format!(
+            "{STYLE_INSERTED}Only in {}: {}{STYLE_INSERTED:#}",
+            right.display(),
+            path.display()
+        )),
+        (Some(EntryKind::Symlink), Some(EntryKind::Symlink)) => FileChange::Rendered(format!(
+            "Symlinks {} and {} point to different targets\n{}",
+            left.join(path).display(),
+            right.join(path).display(),
+            compute_diff(&left_text, &right_text)?
+        )),
+        (Some(left_kind), Some(right_kind)) if left_kind != right_kind => {
+            let describe = |kind| match kind {
+                EntryKind::File => "a regular file",
+                EntryKind::Symlink => "a symlink",
+            };
+            FileChange::Rendered(format!(
+                "{} is {} while {} is {}",
+                left.join(path).display(),
+                describe(left_kind),
+                right.join(path).display(),
+                describe(right_kind)
+            ))
+        }
+        _ => FileChange::Rendered(format!(
+            "diff {} {}\n{}",
+            left.join(path).display(),
+            right.join(path).display(),
+            options.diff.diff(&left_text, &right_text)?
+        )),
+    })
+}
+
+/// Prints the differences between two trees, returning whether there were
+/// any.
+///
+/// Files that cannot be compared are reported in place of their diffs and
+/// fail the comparison once all others are printed, unless `strict` is set,
+/// in which case the first one fails it right away.
+pub fn dirs_diff(
+    out: &mut impl Write,
+    left: &Path,
+    right: &Path,
+    options: &DirOptions,
+) -> Result<bool> {
+    let left_files = walk(left, options)?;
+    let right_files = walk(right, options)?;
+    let paths = left_files
+        .keys()
+        .chain(right_files.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|path| {
+            let kinds = (
+                left_files.get(path).copied(),
+                right_files.get(path).copied(),
+            );
+            (path.clone(), kinds)
+        })
+        .collect::<Vec<_>>();
+
+    let changes = parallel_map(&paths, options.jobs, |(path, kinds)| {
+        diff_file(left, right, path, *kinds, options)
+    });
+
+    let mut stats = Vec::new();
+    let mut summary = DiffSummary::default();
+    let mut any_changed = false;
+    let mut errors = 0;
+    let end = if options.name_only && options.null_data {
+        '\0'
+    } else {
+        '\n'
+    };
+    for (change, (path, _)) in changes.into_iter().zip(&paths) {
+        let change = match change {
+            Err(err) if !options.strict => {
+                writeln!(
+                    out,
+                    "{STYLE_DELETED}Failed to compare {}:{STYLE_DELETED:#} {err:#}",
+                    path.display()
+                )?;
+                errors += 1;
+                continue;
+            }
+            change => change?,
+        };
+        any_changed |= !matches!(change, FileChange::Unchanged(_));
+        match change {
+            FileChange::Unchanged(unchanged) => summary += unchanged,
+            FileChange::Rendered(output) => write!(out, "{output}{end}")?,
+            FileChange::Stat(stat) => {
+                summary += stat.summary;
+                stats.push(stat);
+            }
+        }
+    }
+
+    if options.stat {
+        print_stat(out, &stats, summary)?;
+    }
+    if errors > 0 {
+        anyhow::bail!("{} could not be compared", plural(errors, "file", "files"));
+    }
+    Ok(any_changed)
+}
+
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    format!("{count} {}", if count == 1 { singular } else { plural })
+}
+
+/// Prints a `git diff --stat` style summary of the changed files, ending
+/// with totals and the changed share of all compared content.
+pub fn print_stat(
+    out: &mut impl Write,
+    stats: &[FileStat],
+    summary: DiffSummary,
+) -> std::io::Result<()> {
+    let name_width = stats
+        .iter()
+        .map(|stat| stat.path.display().to_string().len())
+        .max()
+        .unwrap_or_default();
+    let count_width = stats
+        .iter()
+        .map(|stat| (stat.insertions + stat.deletions).to_string().len())
+        .max()
+        .unwrap_or_default();
+    let max_changes = stats
+        .iter()
+        .map(|stat| stat.insertions + stat.deletions)
+        .max()
+        .unwrap_or_default();
+
+    for stat in stats {
+        let changes = stat.insertions + stat.deletions;
+        let (insertions, deletions) = if max_changes > STAT_WIDTH {
+            (
+                (stat.insertions * STAT_WIDTH).div_ceil(max_changes),
+                (stat.deletions * STAT_WIDTH).div_ceil(max_changes),
+            )
+        } else {
+            (stat.insertions, stat.deletions)
+        };
+        writeln!(
+            out,
+            " {:name_width$} | {changes:>count_width$} {STYLE_INSERTED}{}{STYLE_INSERTED:#}{STYLE_DELETED}{}{STYLE_DELETED:#}",
+            stat.path.display(),
+            "+".repeat(insertions),
+            "-".repeat(deletions),
+        )?;
+    }
+
+    writeln!(
+        out,
+        " {} changed, {}, {}, {}",
+        plural(stats.len(), "file", "files"),
+        plural(
+            stats.iter().map(|stat| stat.insertions).sum(),
+            "insertion(+)",
+            "insertions(+)"
+        ),
+        plural(
+            stats.iter().map(|stat| stat.deletions).sum(),
+            "deletion(-)",
+            "deletions(-)"
+        ),
+        summary.changed_share(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> DirOptions {
+        DirOptions {
+            stat: false,
+            jobs: 1,
+            follow_symlinks: false,
+            strict: false,
+            name_only: false,
+            null_data: false,
+            filter: PathFilter::new(&[], &[]).unwrap(),
+            read: ReadOptions {
+                decompress: true,
+                max_size: None,
+                timeout: None,
+                encoding: encoding_rs::UTF_8,
+            },
+            normalization: Normalization::default(),
+            diff: DiffOptions::default(),
+        }
+    }
+
+    /// Compares the trees, returning whether they differ and the output.
+    fn compare(left: &Path, right: &Path, options: &DirOptions) -> (bool, String) {
+        let mut out = Vec::new();
+        let changed = dirs_diff(&mut out, left, right, options).unwrap();
+        (changed, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn parallel_comparison_prints_files_in_path_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        for index in 0..50 {
+            let name = format!("dir{}/file{index:02}", index % 3);
+            std::fs::create_dir_all(left.join(&name).parent().unwrap()).unwrap();
+            std::fs::create_dir_all(right.join(&name).parent().unwrap()).unwrap();
+            std::fs::write(left.join(&name), format!("{index}\n")).unwrap();
+            std::fs::write(right.join(&name), format!("{}\n", index * 2)).unwrap();
+        }
+
+        let (changed, sequential) = compare(&left, &right, &options());
+        assert!(changed);
+        let headers = sequential
+            .lines()
+            .filter(|line| line.starts_with("diff "))
+            .collect::<Vec<_>>();
+        assert_eq!(headers.len(), 49, "only file00 is unchanged");
+        assert!(headers.is_sorted());
+        for _ in 0..5 {
+            let parallel = DirOptions {
+                jobs: 8,
+                ..options()
+            };
+            assert_eq!(compare(&left, &right, &parallel).1, sequential);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_compare_by_target_unless_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        for (root, target) in [(&left, "a"), (&right, "b")] {
+            std::fs::create_dir(root).unwrap();
+            std::fs::write(root.join("a"), "x\n").unwrap();
+            std::fs::write(root.join("b"), "y\n").unwrap();
+            std::os::unix::fs::symlink(target, root.join("link")).unwrap();
+            std::os::unix::fs::symlink(".", root.join("loop")).unwrap();
+        }
+
+        let (changed, out) = compare(&left, &right, &options());
+        assert!(changed);
+        assert!(out.starts_with(&format!(
+            "Symlinks {} and {} point to different targets\n",
+            left.join("link").display(),
+            right.join("link").display()
+        )));
+        assert_eq!(out.lines().count(), 2, "loop points to . on both sides");
+
+        let follow = DirOptions {
+            follow_symlinks: true,
+            ..options()
+        };
+        let (changed, out) = compare(&left, &right, &follow);
+        assert!(changed);
+        assert!(out.starts_with(&format!(
+            "diff {} {}\n",
+            left.join("link").display(),
+            right.join("link").display()
+        )));
+        assert!(!out.contains("loop"));
+    }
+
+    #[test]
+    fn excluded_files_are_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        for (root, text) in [(&left, "a\n"), (&right, "b\n")] {
+            std::fs::create_dir_all(root.join("sub")).unwrap();
+            std::fs::write(root.join("kept.txt"), text).unwrap();
+            std::fs::write(root.join("sub/build.log"), text).unwrap();
+        }
+        let options = DirOptions {
+            filter: PathFilter::new(&[], &["*.log".to_owned()]).unwrap(),
+            ..options()
+        };
+        let (changed, out) = compare(&left, &right, &options);
+        assert!(changed);
+        assert!(out.contains("kept.txt"));
+        assert!(!out.contains("build.log"));
+    }
+
+    #[test]
+    fn unreadable_files_are_reported_while_the_rest_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        for (root, text) in [(&left, "a\n"), (&right, "b\n")] {
+            std::fs::create_dir(root).unwrap();
+            std::fs::write(root.join("data.bin"), b"\xff\xfe\n").unwrap();
+            std::fs::write(root.join("text"), text).unwrap();
+        }
+
+        let mut out = Vec::new();
+        let err = dirs_diff(&mut out, &left, &right, &options()).unwrap_err();
+        assert_eq!(err.to_string(), "1 file could not be compared");
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Failed to compare data.bin:"), "{out}");
+        let text = format!(
+            "diff {} {}\n",
+            left.join("text").display(),
+            right.join("text").display()
+        );
+        assert!(out.contains(&text), "{out}");
+
+        let strict = DirOptions {
+            strict: true,
+            ..options()
+        };
+        let mut out = Vec::new();
+        assert!(dirs_diff(&mut out, &left, &right, &strict).is_err());
+        assert!(out.is_empty(), "the walk stops at data.bin");
+    }
+
+    #[test]
+    fn names_end_with_nul_given_null_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        for (root, text) in [(&left, "a\n"), (&right, "b\n")] {
+            std::fs::create_dir(root).unwrap();
+            std::fs::write(root.join("changed"), text).unwrap();
+            std::fs::write(root.join("same"), "x\n").unwrap();
+        }
+        std::fs::write(right.join("new"), "y\n").unwrap();
+        let name_only = DirOptions {
+            name_only: true,
+            ..options()
+        };
+        assert_eq!(
+            compare(&left, &right, &name_only),
+            (true, "M\tchanged\nA\tnew\n".to_owned())
+        );
+        let null_data = DirOptions {
+            null_data: true,
+            ..name_only
+        };
+        assert_eq!(
+            compare(&left, &right, &null_data),
+            (true, "M\tchanged\0A\tnew\0".to_owned())
+        );
+    }
+
+    #[test]
+    fn name_only_lists_changed_paths_within_the_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        for (root, text) in [(&left, "a\n"), (&right, "b\n")] {
+            std::fs::create_dir_all(root.join("src")).unwrap();
+            std::fs::write(root.join("src/lib.rs"), text).unwrap();
+            std::fs::write(root.join("src/same.rs"), "x\n").unwrap();
+            std::fs::write(root.join("build.log"), text).unwrap();
+        }
+        std::fs::write(left.join("src/old.rs"), "y\n").unwrap();
+        std::fs::write(right.join("README"), "z\n").unwrap();
+        let options = DirOptions {
+            name_only: true,
+            filter: PathFilter::new(&[], &["*.log".to_owned()]).unwrap(),
+            ..options()
+        };
+        assert_eq!(
+            compare(&left, &right, &options),
+            (true, "A\tREADME\nM\tsrc/lib.rs\nD\tsrc/old.rs\n".to_owned())
+        );
+    }
+}
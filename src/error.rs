@@ -0,0 +1,98 @@
+//! Error kinds that callers may want to tell apart.
+
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiffError {
+    /// Reading a file or talking to a child process failed
+    #[error("{context}")]
+    IoError {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// An input could not be interpreted, e.g. a malformed test file
+    #[error("{context}")]
+    ParseError {
+        context: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The diff engine rejected its input
+    #[error("Diff computation failed: {0:?}")]
+    DiffEngineError(diff_match_patch_rs::Error),
+    /// A program under test or a helper command could not be started
+    #[error("Failed to start program: {}", program.display())]
+    SpawnFailed {
+        program: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A URL input could not be fetched, or the server answered with an error
+    #[error("Failed to fetch {url}")]
+    FetchFailed {
+        url: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A program was still running at the `--suite-timeout` deadline
+    #[error("{} was stopped at the suite timeout", program.display())]
+    Timeout { program: PathBuf },
+    /// An input file exceeds `--max-input-size`
+    #[error(
+        "{} is {size} bytes, over the limit of {limit} bytes (use --force-large to diff it anyway)",
+        path.display()
+    )]
+    InputTooLarge {
+        path: PathBuf,
+        size: u64,
+        limit: u64,
+    },
+}
+
+impl DiffError {
+    /// Wraps an I/O error with a description of the failed operation, for
+    /// use with `map_err`.
+    pub fn io(context: impl Into<String>) -> impl FnOnce(std::io::Error) -> Self {
+        let context = context.into();
+        move |source| Self::IoError { context, source }
+    }
+
+    /// Wraps a parse error with a description of what was being parsed, for
+    /// use with `map_err`.
+    pub fn parse<E: std::error::Error + Send + Sync + 'static>(
+        context: impl Into<String>,
+    ) -> impl FnOnce(E) -> Self {
+        let context = context.into();
+        move |source| Self::ParseError {
+            context,
+            source: Box::new(source),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constructors_keep_the_context_and_the_source() {
+        let error = DiffError::io("Failed to read file: a")(std::io::Error::other("gone"));
+        assert_eq!(error.to_string(), "Failed to read file: a");
+        assert!(matches!(
+            &error,
+            DiffError::IoError { source, .. } if source.to_string() == "gone"
+        ));
+
+        let source = "x".parse::<u32>().unwrap_err();
+        let error = DiffError::parse("Failed to parse count")(source);
+        assert!(matches!(
+            &error,
+            DiffError::ParseError { context, .. } if context == "Failed to parse count"
+        ));
+        assert_eq!(
+            std::error::Error::source(&error).unwrap().to_string(),
+            "invalid digit found in string"
+        );
+    }
+}
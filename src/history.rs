@@ -0,0 +1,144 @@
+//! Per-case runtimes of earlier runs, kept in a JSON-lines file.
+
+use crate::error::DiffError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Number of most recent runs averaged over.
+const AVERAGED_RUNS: usize = 20;
+
+/// One line of the history file.
+#[derive(Deserialize, Serialize)]
+struct Run {
+    /// Runtime in microseconds by case note
+    cases: BTreeMap<String, u64>,
+}
+
+pub struct History {
+    path: PathBuf,
+    /// Runs in the file, oldest first
+    runs: Vec<Run>,
+}
+
+impl History {
+    /// Reads the history file, which need not exist yet; unreadable lines
+    /// are skipped.
+    pub fn load(path: &Path) -> Result<Self, DiffError> {
+        let runs = match std::fs::read_to_string(path) {
+            Ok(text) => text
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                return Err(DiffError::io(format!(
+                    "Failed to read history file: {}",
+                    path.display()
+                ))(err));
+            }
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            runs,
+        })
+    }
+
+    /// The mean runtime of the case over the last `AVERAGED_RUNS` runs that
+    /// ran it.
+    pub fn average(&self, note: &str) -> Option<Duration> {
+        let samples = self
+            .runs
+            .iter()
+            .rev()
+            .filter_map(|run| run.cases.get(note))
+            .take(AVERAGED_RUNS)
+            .collect::<Vec<_>>();
+        let count = u64::try_from(samples.len())
+            .ok()
+            .filter(|&count| count > 0)?;
+        Some(Duration::from_micros(
+            samples.into_iter().sum::<u64>() / count,
+        ))
+    }
+
+    /// Appends a run to the file, first dropping all but the last
+    /// `AVERAGED_RUNS` runs once it holds twice as many.
+    pub fn record<'a>(
+        &mut self,
+        cases: impl Iterator<Item = (&'a str, Duration)>,
+    ) -> Result<(), DiffError> {
+        let run = Run {
+            cases: cases
+                .map(|(note, duration)| {
+                    let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+                    (note.to_owned(), micros)
+                })
+                .collect(),
+        };
+        let line = |run: &Run| serde_json::to_string(run).expect("runs serialize to JSON") + "\n";
+        let write_error = DiffError::io(format!(
+            "Failed to write history file: {}",
+            self.path.display()
+        ));
+
+        if self.runs.len() + 1 >= 2 * AVERAGED_RUNS {
+            self.runs.drain(..=self.runs.len() - AVERAGED_RUNS);
+            self.runs.push(run);
+            let text = self.runs.iter().map(line).collect::<String>();
+            std::fs::write(&self.path, text).map_err(write_error)
+        } else {
+            let text = line(&run);
+            self.runs.push(run);
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .and_then(|mut file| file.write_all(text.as_bytes()))
+                .map_err(write_error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_run_averages_over_the_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let mut history = History::load(&path).unwrap();
+        assert_eq!(history.average("case"), None);
+        history
+            .record([("case", Duration::from_millis(40))].into_iter())
+            .unwrap();
+
+        let mut history = History::load(&path).unwrap();
+        assert_eq!(history.average("case"), Some(Duration::from_millis(40)));
+        history
+            .record([("case", Duration::from_millis(60))].into_iter())
+            .unwrap();
+        let history = History::load(&path).unwrap();
+        assert_eq!(history.average("case"), Some(Duration::from_millis(50)));
+        assert_eq!(history.average("other"), None);
+    }
+
+    #[test]
+    fn file_keeps_only_recent_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        for millis in 0..2 * AVERAGED_RUNS as u64 {
+            let mut history = History::load(&path).unwrap();
+            history
+                .record([("case", Duration::from_millis(millis))].into_iter())
+                .unwrap();
+        }
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines < 2 * AVERAGED_RUNS, "{lines} lines");
+    }
+}
@@ -0,0 +1,111 @@
+//! The subset of JSON path syntax accepted by `--json-path`: `$` followed by
+//! `.key`, `['key']` and `[index]` steps.
+
+use serde_json::Value;
+use std::fmt::Write;
+
+#[derive(Clone, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// A path to a single value in a JSON document.
+#[derive(Clone)]
+pub struct JsonPath {
+    /// The path as given, used to label its value
+    text: String,
+    steps: Vec<Step>,
+}
+
+impl JsonPath {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rest = text
+            .strip_prefix('$')
+            .ok_or_else(|| format!("JSON path must start with `$`: {text}"))?;
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("['") {
+                let (key, after) = after
+                    .split_once("']")
+                    .ok_or_else(|| format!("Unclosed `['` in JSON path: {text}"))?;
+                steps.push(Step::Key(key.to_owned()));
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let (index, after) = after
+                    .split_once(']')
+                    .ok_or_else(|| format!("Unclosed `[` in JSON path: {text}"))?;
+                let index = index
+                    .parse()
+                    .map_err(|_| format!("Invalid index {index:?} in JSON path: {text}"))?;
+                steps.push(Step::Index(index));
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(format!("Empty key in JSON path: {text}"));
+                }
+                steps.push(Step::Key(after[..end].to_owned()));
+                rest = &after[end..];
+            } else {
+                return Err(format!("Expected `.` or `[` in JSON path: {text}"));
+            }
+        }
+        Ok(Self {
+            text: text.to_owned(),
+            steps,
+        })
+    }
+
+    fn select<'a>(&self, document: &'a Value) -> Option<&'a Value> {
+        self.steps
+            .iter()
+            .try_fold(document, |value, step| match step {
+                Step::Key(key) => value.get(key),
+                Step::Index(index) => value.get(index),
+            })
+    }
+}
+
+/// One `path = value` line per path, with `path is missing` for paths that
+/// lead nowhere, or `None` if `text` is not JSON.
+pub fn select(paths: &[JsonPath], text: &str) -> Option<String> {
+    let document = serde_json::from_str::<Value>(text).ok()?;
+    let mut lines = String::new();
+    for path in paths {
+        let _ = match path.select(&document) {
+            Some(value) => writeln!(lines, "{} = {value}", path.text),
+            None => writeln!(lines, "{} is missing", path.text),
+        };
+    }
+    Some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_selected_values_are_compared() {
+        let paths = [JsonPath::parse("$.result.id").unwrap()];
+        let left = r#"{"result": {"id": 7, "took": 12}, "server": "a"}"#;
+        let right = r#"{"server": "b", "result": {"took": 30, "id": 7}}"#;
+        assert_eq!(select(&paths, left).unwrap(), "$.result.id = 7\n");
+        assert_eq!(select(&paths, left), select(&paths, right));
+
+        let other = r#"{"result": {"id": 8}}"#;
+        assert_eq!(select(&paths, other).unwrap(), "$.result.id = 8\n");
+        assert_eq!(select(&paths, "{}").unwrap(), "$.result.id is missing\n");
+        assert_eq!(select(&paths, "not json"), None);
+    }
+
+    #[test]
+    fn paths_step_through_keys_and_indices() {
+        let path = JsonPath::parse("$.items[1]['full name']").unwrap();
+        let document = serde_json::json!({"items": [{}, {"full name": "Ada"}]});
+        assert_eq!(path.select(&document), Some(&Value::from("Ada")));
+        for invalid in ["items", "$.", "$[x]", "$['key", "$items"] {
+            assert!(JsonPath::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! The parts of the `diff` tool that other programs may want to use.
+
+pub mod error;
+
+pub use error::DiffError;
@@ -1,33 +1,534 @@
+mod decode;
+mod dir;
+mod history;
+mod json_path;
+mod myers;
+mod ndiff;
+mod normalize;
+mod oneline;
+mod parallel;
+mod printer;
+mod progress;
+mod records;
+mod report;
+mod theme;
+mod unified;
+
 use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
-use diff_match_patch_rs::{Compat, DiffMatchPatch, Ops, dmp};
+use clap::{CommandFactory, Parser, ValueEnum, error::ErrorKind};
+use decode::Decoding;
+use diff::error;
+use diff_match_patch_rs::{Compat, DiffMatchPatch, Efficient, Ops, dmp::Diff};
+use encoding_rs::Encoding;
+use error::DiffError;
+use history::History;
+use normalize::Normalization;
+use printer::OrderedPrinter;
+use progress::Progress;
+use regex::Regex;
+use report::Report;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::Digest;
 use std::{
-    io::Write,
-    process::{Command, Stdio},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ffi::{OsStr, OsString},
+    io::{IsTerminal, Read, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::{
+        Mutex, PoisonError,
+        mpsc::{self, Receiver},
+    },
+    time::{Duration, Instant, UNIX_EPOCH},
 };
+use theme::{Role, Theme};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+    /// Print what each exit status means and exit
+    #[clap(long, hide = true)]
+    print_exit_codes: bool,
+    /// Log more details to stderr; repeat for more, or set `RUST_LOG` instead
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Colors and markers of the output
+    #[clap(long, value_enum, global = true, default_value_t = Theme::Default)]
+    theme: Theme,
 }
 
 #[derive(clap::Subcommand)]
 enum Commands {
     /// Get the diff between two inputs
-    Diff {
-        /// Left input (app or file or string)
-        left: String,
-        /// Right input (file or string)
-        right: String,
-        /// Compare mode
-        #[clap(short, long, value_enum, default_value_t = Mode::Interactive)]
-        mode: Mode,
+    Diff(Box<DiffArgs>),
+    /// Run two programs on the same test cases and compare their outputs
+    Differential {
+        /// Program whose output is taken as expected
+        reference: String,
+        /// Program checked against the reference
+        candidate: String,
+        /// YAML file with test cases, whose expected outputs are ignored
+        test_file: String,
     },
     /// Generate example test cases
     Example,
+    /// Run a program with two sets of arguments on the same input and diff the outputs
+    SelfDiff {
+        /// Program to run
+        program: String,
+        /// Arguments of the first run, whose output is taken as expected
+        #[clap(allow_hyphen_values = true)]
+        left_args: String,
+        /// Arguments of the second run
+        #[clap(allow_hyphen_values = true)]
+        right_args: String,
+        /// Text written to the program's stdin on both runs
+        #[clap(long)]
+        input: Option<String>,
+    },
+    /// Print a JSON Schema describing the test file format
+    Schema,
+    /// Rewrite a test file in canonical form, dropping fields left at their defaults
+    Fmt {
+        /// YAML file with test cases
+        test_file: PathBuf,
+        /// Overwrite the file instead of printing the result
+        #[clap(short, long)]
+        in_place: bool,
+    },
+    /// Run a program once and compare its output with an expected string
+    Expect {
+        /// Program to run
+        program: String,
+        /// Expected output
+        expected: String,
+        /// Arguments passed to the program
+        #[clap(long, allow_hyphen_values = true)]
+        args: Option<String>,
+        /// Text written to the program's stdin
+        #[clap(long)]
+        input: Option<String>,
+    },
+}
+
+#[derive(clap::Args)]
+#[allow(clippy::struct_excessive_bools)]
+struct DiffArgs {
+    /// Left input (app or file or string)
+    #[clap(required_unless_present = "stdin")]
+    left: Option<String>,
+    /// Right input (file or string, or test file or fixtures directory)
+    #[clap(required_unless_present_any = ["stdin", "snapshot_dir", "git"])]
+    right: Option<String>,
+    /// Read both sides from stdin, separated by a delimiter line (interactive mode)
+    #[clap(long, conflicts_with_all = ["left", "right"])]
+    stdin: bool,
+    /// Line separating the left and right side on stdin
+    #[clap(long, default_value = "---DIFF---")]
+    delimiter: String,
+    /// Decode both inputs from this encoding of bytes before comparing them (interactive
+    /// mode)
+    #[clap(long, value_name = "ENCODING", default_value = "none")]
+    decode: Decoding,
+    /// Compare hex dumps of the bytes of both inputs, which need not be valid UTF-8
+    /// (interactive mode)
+    #[clap(long)]
+    binary: bool,
+    /// Compare mode
+    #[clap(short, long, value_enum, default_value_t = Mode::Interactive)]
+    mode: Mode,
+    /// Only run test cases whose note contains this text (program mode)
+    #[clap(long)]
+    filter: Option<String>,
+    /// Only compare the values at this JSON path, e.g. `$.result.id` or `$.items[0]`, of
+    /// both sides if they are JSON (repeatable)
+    #[clap(long, value_name = "PATH", value_parser = json_path::JsonPath::parse)]
+    json_path: Vec<json_path::JsonPath>,
+    /// Drop lines matching this regex from both sides before comparing (repeatable)
+    #[clap(long, value_parser = Regex::new)]
+    ignore_regex: Vec<Regex>,
+    /// Drop lines starting with this comment prefix, e.g. `#` or `//`, after any
+    /// indentation from both sides before comparing (repeatable)
+    #[clap(long, value_name = "PREFIX")]
+    ignore_comment: Vec<String>,
+    /// Only compare lines START to END of the left input, e.g. `100:200`, `100:` or `:200`
+    #[clap(long, value_name = "START:END", value_parser = LineRange::parse)]
+    left_range: Option<LineRange>,
+    /// Only compare lines START to END of the right input
+    #[clap(long, value_name = "START:END", value_parser = LineRange::parse)]
+    right_range: Option<LineRange>,
+    /// Stream both files and stop at the first differing line, without diffing them (file mode)
+    #[clap(long, conflicts_with_all = ["first_diff", "stat"])]
+    stop_at_first_diff: bool,
+    /// Wrap inline diff lines, or cut `oneline` ones, at this many columns or at the
+    /// terminal width with `auto`
+    #[clap(long, value_name = "COLUMNS", value_parser = Wrap::parse)]
+    wrap: Option<Wrap>,
+    /// Show spaces as `·` and tabs as `→`, highlighting trailing spaces, in changed text
+    /// or everywhere (inline format)
+    #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "changed")]
+    show_whitespace: Option<Whitespace>,
+    /// Replace unchanged runs longer than this many chars with `…[N chars]…` (inline
+    /// format)
+    #[clap(long, value_name = "CHARS")]
+    fold: Option<usize>,
+    /// Highlight unchanged runs of fewer than N chars between two changes as changed
+    /// too, so that nearby changes read as one (inline format)
+    #[clap(long, value_name = "N")]
+    min_equal_run: Option<usize>,
+    /// Only render the first N segments of a diff with more, noting how many there are
+    /// on stderr
+    #[clap(long, value_name = "N")]
+    max_segments: Option<usize>,
+    /// Print only the text both inputs share, dropping insertions and deletions
+    /// (inline format)
+    #[clap(long)]
+    equal_only: bool,
+    /// Print both inputs as they are before the diff
+    #[clap(long)]
+    show_inputs: bool,
+    /// Only report where the inputs first differ (file and interactive modes)
+    #[clap(long)]
+    first_diff: bool,
+    /// Compare the inputs as single-line `key=value;key=value` records, listing the
+    /// changed, added and removed keys (file and interactive modes)
+    #[clap(long, conflicts_with = "first_diff")]
+    columns: bool,
+    /// Separator between the fields of `--columns` records
+    #[clap(long, value_name = "SEP", default_value = ";", requires = "columns")]
+    field_sep: String,
+    /// Separator between the key and the value of a `--columns` field
+    #[clap(long, value_name = "SEP", default_value = "=", requires = "columns")]
+    pair_sep: String,
+    /// Treat `\` as `/` in both inputs, collapsing runs of backslashes; this also
+    /// rewrites backslashes that are not path separators
+    #[clap(long)]
+    normalize_paths: bool,
+    /// Ignore whitespace, including blank lines, at the start and end of both inputs
+    #[clap(long)]
+    trim: bool,
+    /// Treat runs of spaces and tabs within a line as a single space
+    #[clap(long)]
+    collapse_spaces: bool,
+    /// Compare both inputs as if they were lowercase
+    #[clap(long)]
+    ignore_case: bool,
+    /// Expand tabs in both inputs to spaces up to the next multiple of this many
+    /// columns before comparing
+    #[clap(long, value_name = "N", num_args = 0..=1, default_missing_value = "8")]
+    tabsize: Option<NonZeroUsize>,
+    /// Split both inputs into records at NUL bytes instead of line breaks, like the
+    /// output of `find -print0`, and end output lines with NUL (file and interactive modes,
+    /// and the paths listed by `--name-only`)
+    #[clap(long, conflicts_with_all = ["first_diff", "stop_at_first_diff", "stat"])]
+    null_data: bool,
+    /// Only print the insertion and deletion counts, exiting with 1 if there are any
+    #[clap(long)]
+    count_only: bool,
+    /// Only print the length of the longest unchanged run, in chars or lines
+    #[clap(long, conflicts_with = "count_only")]
+    lcs: bool,
+    /// In unified diffs, end each hunk header with the closest line above the
+    /// hunk matching this regex, e.g. a function signature
+    #[clap(long, value_name = "REGEX", value_parser = Regex::new)]
+    show_section: Option<Regex>,
+    /// Replace matches of this regex on both sides before comparing (repeatable)
+    #[clap(long, value_parser = Regex::new)]
+    mask: Vec<Regex>,
+    /// Replacement for masked text, which may refer to capture groups as `$1` or `${name}`
+    #[clap(long, default_value = "<MASK>")]
+    mask_with: String,
+    /// Unit in which changes are highlighted
+    #[clap(short, long, value_enum, default_value_t = Granularity::Auto)]
+    granularity: Granularity,
+    /// Input size in bytes from which automatic granularity diffs by line
+    #[clap(long, default_value_t = DEFAULT_GRANULARITY_THRESHOLD)]
+    granularity_threshold: usize,
+    /// Highlight the changed characters within similar changed lines of a line diff
+    #[clap(long)]
+    refine: bool,
+    /// Diff engine mode for char diffs
+    #[clap(long, value_enum, default_value_t = Engine::Compat)]
+    engine: Engine,
+    /// Settle for a less minimal diff once a diff has taken this long, or never with
+    /// `0s`
+    #[clap(long, value_name = "DURATION", value_parser = humantime::parse_duration, default_value = "1s")]
+    diff_timeout: Duration,
+    /// Merge consecutive diff segments with the same operation; the default
+    #[clap(long, overrides_with = "no_collate")]
+    collate: bool,
+    /// Keep consecutive diff segments with the same operation apart instead of merging
+    /// them
+    #[clap(long, overrides_with = "collate")]
+    no_collate: bool,
+    /// Algorithm for line diffs
+    #[clap(long, value_enum, default_value_t = Algorithm::Dmp)]
+    algorithm: Algorithm,
+    /// Output format of the diff (file and interactive modes)
+    #[clap(short, long, value_enum, default_value_t = Format::Inline)]
+    format: Format,
+    /// Show a per-file summary of changed lines instead of the diff (file mode)
+    #[clap(long)]
+    stat: bool,
+    /// Number of files or test cases processed in parallel [default: available CPUs for
+    /// files, 1 for test cases]
+    #[clap(short, long)]
+    jobs: Option<NonZeroUsize>,
+    /// Retry starting a program this many times, backing off in between, when the
+    /// system is temporarily out of processes or memory (program mode)
+    #[clap(long, value_name = "COUNT", default_value_t = DEFAULT_SPAWN_RETRIES)]
+    spawn_retries: u32,
+    /// Compare the files symlinks point to instead of their targets (directory comparison)
+    #[clap(long)]
+    follow_symlinks: bool,
+    /// Report files that cannot be read, e.g. binary ones, and compare the rest; the
+    /// default (directory comparison)
+    #[clap(long, overrides_with = "strict")]
+    keep_going: bool,
+    /// Stop at the first file that cannot be read (directory comparison)
+    #[clap(long, overrides_with = "keep_going")]
+    strict: bool,
+    /// Only list the paths of changed files, marked `M`, or `A` and `D` for files only
+    /// in the right or left directory, like `git diff --name-status` (directory
+    /// comparison)
+    #[clap(long, conflicts_with = "stat")]
+    name_only: bool,
+    /// Only compare files matching this glob (directory comparison, repeatable)
+    #[clap(long)]
+    include: Vec<String>,
+    /// Skip files and directories matching this glob (directory comparison, repeatable)
+    #[clap(long)]
+    exclude: Vec<String>,
+    /// Read gzip-compressed files as they are instead of decompressing them (file mode)
+    #[clap(long)]
+    no_decompress: bool,
+    /// Refuse to read input files larger than this many bytes (file mode)
+    #[clap(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_INPUT_SIZE)]
+    max_input_size: u64,
+    /// Read input files regardless of `--max-input-size`
+    #[clap(long)]
+    force_large: bool,
+    /// Decode input files from this encoding, e.g. `latin1` or `utf-16le`, instead of UTF-8
+    /// (file mode)
+    #[clap(long, value_name = "LABEL", value_parser = parse_encoding)]
+    encoding: Option<&'static Encoding>,
+    /// Give up fetching an input given as an http(s) URL after this long, e.g. `30s`
+    /// (file mode)
+    #[clap(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+    /// Don't print the `---`/`+++` header with the file paths (file mode)
+    #[clap(long)]
+    no_header: bool,
+    /// Include modification times in the file header (file mode)
+    #[clap(long)]
+    show_times: bool,
+    /// Open the diff in $EDITOR instead of printing it (file and interactive modes)
+    #[clap(long)]
+    edit: bool,
+    /// Also copy the uncolored diff to the system clipboard (file and interactive modes)
+    #[clap(long)]
+    clipboard: bool,
+    /// Measure program run times instead of comparing outputs (program mode)
+    #[clap(long)]
+    bench: bool,
+    /// Number of runs per test case when benchmarking
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    bench_runs: u32,
+    /// Print a histogram of test case run times after the run (program mode)
+    #[clap(long)]
+    timing_histogram: bool,
+    /// After the summary, list the notes of failed, timed out and skipped cases under
+    /// a heading each (program mode)
+    #[clap(long)]
+    group_summary: bool,
+    /// Show how many cases have finished and about how long the rest will take on
+    /// stderr, if it is a terminal (program mode)
+    #[clap(long)]
+    progress: bool,
+    /// Leave unknown `{{NAME}}` placeholders as-is instead of failing (program mode)
+    #[clap(long)]
+    allow_unresolved: bool,
+    /// Expand `$VAR` and `${VAR}` from the environment in the program path and
+    /// in test case args and input (program mode)
+    #[clap(long)]
+    expand_env: bool,
+    /// Fail on unset variables instead of expanding them to nothing
+    #[clap(long, requires = "expand_env")]
+    strict_env: bool,
+    /// Fail cases whose output is not valid UTF-8 instead of decoding it lossily (program mode)
+    #[clap(long)]
+    strict_utf8: bool,
+    /// Compare what the program writes to stdout and stderr as one interleaved
+    /// stream (program mode)
+    #[clap(long)]
+    merge_stderr: bool,
+    /// Fail cases whose program exits with a non-zero status, unless they set
+    /// `allow_nonzero_exit` (program mode)
+    #[clap(long)]
+    require_success: bool,
+    /// Print only the final summary instead of every case (program mode)
+    #[clap(long)]
+    summary_only: bool,
+    /// Print one `STATUS<TAB>MILLISECONDS<TAB>NOTE` line per case instead, in a
+    /// format that stays stable across versions (program mode)
+    #[clap(long, conflicts_with = "summary_only")]
+    porcelain: bool,
+    /// Print a JSON object per line as each case starts and finishes instead (program
+    /// mode)
+    #[clap(long, conflicts_with_all = ["summary_only", "porcelain"])]
+    events: bool,
+    /// Show a `.`, `F`, `T` or `S` on stderr as each case passes, fails, times out or is
+    /// skipped, and only the reports of failed cases after the run, if stderr is a
+    /// terminal (program mode)
+    #[clap(long, conflicts_with_all = ["summary_only", "porcelain", "events", "progress"])]
+    dots: bool,
+    /// Stop the run after this long, e.g. `5m`, leaving the remaining cases
+    /// unrun and exiting with status 124 (program mode)
+    #[clap(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    suite_timeout: Option<Duration>,
+    /// Order in which the cases are listed in the output and the `.json` report; they
+    /// still run in source order (program mode)
+    #[clap(long, value_name = "ORDER", default_value = "source")]
+    sort: SortOrder,
+    /// Also write the summary to this file, or per-case results if it ends in
+    /// `.json` (program mode)
+    #[clap(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// Indent the JSON diff and `.json` report; the default if stdout is a terminal
+    #[clap(long, overrides_with = "json_compact")]
+    json_pretty: bool,
+    /// Print the JSON diff and write the `.json` report on a single line; the default
+    /// if stdout is not a terminal
+    #[clap(long, overrides_with = "json_pretty")]
+    json_compact: bool,
+    /// Run only the cases that failed according to this `.json` report of an
+    /// earlier run (program mode)
+    #[clap(long, value_name = "REPORT")]
+    rerun_failures: Option<PathBuf>,
+    /// Run only the cases of a fixtures directory with files changed since this git
+    /// revision, or all of them outside a git repository (program mode)
+    #[clap(long, value_name = "REV")]
+    since: Option<String>,
+    /// List the cases that started failing or passing since the run of this `.json`
+    /// report (program mode)
+    #[clap(long, value_name = "REPORT")]
+    baseline: Option<PathBuf>,
+    /// Run only the cases of this group of a test file with `groups` (program mode)
+    #[clap(long, value_name = "NAME")]
+    group: Option<String>,
+    /// Reuse program outputs stored in this directory by earlier runs with the
+    /// same program binary, arguments, input and environment (program mode)
+    #[clap(long, value_name = "DIR")]
+    cache: Option<PathBuf>,
+    /// Write the actual output of failed cases to the test file as their `out` (program mode)
+    #[clap(long)]
+    update: bool,
+    /// Like `--update`, but ask on the terminal before updating each case
+    #[clap(long)]
+    interactive: bool,
+    /// Like `--update`, but only for the cases that passed in the `--baseline` run,
+    /// leaving earlier failures alone (program mode)
+    #[clap(long, requires = "baseline", conflicts_with = "update")]
+    accept_regressions: bool,
+    /// Show each case's runtime against its average in this file of earlier runs, then
+    /// record this run in it (program mode)
+    #[clap(long, value_name = "FILE")]
+    history: Option<PathBuf>,
+    /// Run this command after each failed case, with the note in `DIFF_CASE_NOTE` and
+    /// the case's report on stdin (program mode)
+    #[clap(long, value_name = "COMMAND")]
+    on_failure: Option<String>,
+    /// Decide whether outputs match by running this command with the expected and the
+    /// actual output, separated by a NUL byte, on stdin, exit status 0 meaning equal
+    /// (program mode)
+    #[clap(long, value_name = "COMMAND")]
+    equal_via: Option<String>,
+    /// Load environment variables for every test case from this file (program mode)
+    #[clap(long)]
+    env_file: Option<PathBuf>,
+    /// Compare the left input with its snapshot in this directory instead of a right
+    /// input, storing it as the snapshot if there is none yet (file and interactive modes)
+    #[clap(long, value_name = "DIR", requires = "name", conflicts_with_all = ["right", "stdin"])]
+    snapshot_dir: Option<PathBuf>,
+    /// Name of the snapshot in `--snapshot-dir`
+    #[clap(long, requires = "snapshot_dir")]
+    name: Option<String>,
+    /// Store the left input as the snapshot even if one exists
+    #[clap(long, requires = "snapshot_dir")]
+    update_snapshots: bool,
+    /// Compare the left input as committed at this git revision, e.g. `HEAD`, with the
+    /// file in the working tree instead of a right input (file mode)
+    #[clap(long, value_name = "REV", conflicts_with_all = ["right", "stdin", "snapshot_dir"])]
+    git: Option<String>,
+}
+
+impl DiffArgs {
+    /// Whether JSON output is indented, by default only for a terminal.
+    fn json_pretty(&self) -> bool {
+        if self.json_pretty || self.json_compact {
+            self.json_pretty
+        } else {
+            std::io::stdout().is_terminal()
+        }
+    }
+
+    fn diff_options(&self) -> DiffOptions {
+        DiffOptions {
+            granularity: self.granularity,
+            granularity_threshold: self.granularity_threshold,
+            refine: self.refine,
+            engine: self.engine,
+            algorithm: self.algorithm,
+            timeout: self.diff_timeout,
+            collate: !self.no_collate,
+        }
+    }
+
+    /// The options of a directory comparison.
+    fn dir_options(
+        &self,
+        read: ReadOptions,
+        normalization: Normalization,
+        diff: DiffOptions,
+    ) -> Result<dir::DirOptions> {
+        let jobs = self.jobs.map_or_else(
+            || std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            NonZeroUsize::get,
+        );
+        Ok(dir::DirOptions {
+            stat: self.stat,
+            jobs,
+            follow_symlinks: self.follow_symlinks,
+            strict: self.strict,
+            name_only: self.name_only,
+            null_data: self.null_data,
+            filter: dir::PathFilter::new(&self.include, &self.exclude)?,
+            read,
+            normalization,
+            diff,
+        })
+    }
+
+    /// The normalizations applied to both inputs.
+    fn normalization(&self) -> Normalization {
+        Normalization {
+            json_paths: self.json_path.clone(),
+            ignore_regex: self.ignore_regex.clone(),
+            ignore_comment: self.ignore_comment.clone(),
+            mask: self.mask.clone(),
+            mask_with: self.mask_with.clone(),
+            normalize_paths: self.normalize_paths,
+            trim: self.trim,
+            collapse_spaces: self.collapse_spaces,
+            ignore_case: self.ignore_case,
+            tabsize: self.tabsize,
+        }
+    }
 }
 
 #[derive(ValueEnum, Clone)]
@@ -40,154 +541,6074 @@ enum Mode {
     File,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(ValueEnum, Clone, Copy)]
+enum Format {
+    /// Highlight changed characters in place
+    Inline,
+    /// Changed lines with surrounding context, like `diff -u`
+    Unified,
+    /// Every line prefixed, with `?` guides marking changes, like Python's `difflib.ndiff`
+    Ndiff,
+    /// Unified diff with `a/` and `b/` file headers, for `patch -p1` or `git apply`
+    Patch,
+    /// One line per changed region with its line number, cut to the terminal width
+    Oneline,
+    /// Machine-readable list of diff segments
+    Json,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, JsonSchema)]
+#[allow(clippy::struct_excessive_bools)]
 struct TestCase {
+    #[serde(skip_serializing_if = "Option::is_none")]
     note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     args: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     input: Option<String>,
+    /// Inputs fed to stdin one after another in a single run, each ended
+    /// with a newline if it lacks one, e.g. answers to a REPL's prompts; each
+    /// one after the first is written once the program has replied to the
+    /// one before; excludes `input`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    inputs: Vec<String>,
+    /// Command whose output is used as the input instead of `input`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     out: Option<String>,
+    /// File holding the expected output, relative to the test file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    out_file: Option<PathBuf>,
+    /// Command whose output is used as the expected output instead of `out`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ref_command: Option<String>,
+    /// Environment variables set for the program, overriding env files
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    /// Working directory of the program and the case's helper commands,
+    /// relative to the test file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cwd: Option<String>,
+    /// The output is expected not to match, e.g. for a known bug
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    should_fail: bool,
+    /// The case is listed but not run
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    skip: bool,
+    /// Acceptable outputs, any of which passes the case; excludes `out`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    out_any: Vec<String>,
+    /// Substrings that must all appear in the output, wherever they are;
+    /// excludes `out`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    out_contains: Vec<String>,
+    /// Text the output must start with; excludes `out`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    out_prefix: Option<String>,
+    /// Text the output must end with; excludes `out`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    out_suffix: Option<String>,
+    /// Stderr is captured along with stdout, interleaved as it is written
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    merge_stderr: bool,
+    /// The case is judged by its output alone, even with `--require-success`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    allow_nonzero_exit: bool,
+    /// Overrides `--trim` for the case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trim: Option<bool>,
+    /// Overrides `--collapse-spaces` for the case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collapse_spaces: Option<bool>,
+    /// Overrides `--normalize-paths` for the case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normalize_paths: Option<bool>,
+    /// Overrides `--ignore-case` for the case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ignore_case: Option<bool>,
+    /// Overrides `--tabsize` for the case, with 0 leaving tabs alone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tabsize: Option<usize>,
 }
 
-#[derive(Deserialize, Serialize)]
+/// The contents of a test file.
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
 struct TestSuite {
+    /// Values for `{{NAME}}` placeholders in test cases
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    vars: BTreeMap<String, String>,
+    /// `KEY=VALUE` file loaded into the environment of every case, relative
+    /// to the test file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env_file: Option<PathBuf>,
+    /// Expected output of cases with neither `out` nor `out_file`, relative
+    /// to the test file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    out_file: Option<PathBuf>,
+    #[serde(flatten)]
+    cases: Cases,
+}
+
+/// The cases of a suite, either in one list or in named groups.
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(untagged)]
+enum Cases {
+    Flat(Group),
+    Grouped(Groups),
+}
+
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+struct Group {
     tests: Vec<TestCase>,
 }
 
-const STYLE_RED: anstyle::Style =
-    anstyle::Style::new().bg_color(Some(anstyle::Color::Ansi(anstyle::AnsiColor::Red)));
-const STYLE_GREEN: anstyle::Style =
-    anstyle::Style::new().bg_color(Some(anstyle::Color::Ansi(anstyle::AnsiColor::Green)));
-const STYLE_CYAN: anstyle::Style =
-    anstyle::Style::new().bg_color(Some(anstyle::Color::Ansi(anstyle::AnsiColor::Cyan)));
+#[derive(Deserialize, Serialize, Clone, JsonSchema)]
+struct Groups {
+    groups: BTreeMap<String, Group>,
+}
+
+impl TestCase {
+    fn note(&self) -> &str {
+        self.note.as_deref().unwrap_or("Unnamed test case")
+    }
+
+    /// Whether only parts of the output are checked, by `out_contains`,
+    /// `out_prefix` or `out_suffix`.
+    fn is_partial(&self) -> bool {
+        !self.out_contains.is_empty() || self.out_prefix.is_some() || self.out_suffix.is_some()
+    }
+
+    /// The writes to the program's stdin: each of the `inputs`, or else the
+    /// whole `input`.
+    fn stdin(&self) -> Vec<&str> {
+        if self.inputs.is_empty() {
+            self.input.as_deref().into_iter().collect()
+        } else {
+            self.inputs.iter().map(String::as_str).collect()
+        }
+    }
+}
+
+impl TestSuite {
+    /// Reads a suite from YAML, resolving `&anchor`/`*alias` references and
+    /// `<<` merge keys, which may merge one mapping or a list of them.
+    fn load(path: impl AsRef<Path>) -> Result<Self, DiffError> {
+        let file = std::fs::File::open(path).map_err(DiffError::io("Failed to open test file"))?;
+        let mut suite = serde_yaml::from_reader::<_, serde_yaml::Value>(file)
+            .map_err(DiffError::parse("Failed to parse test file"))?;
+        suite.apply_merge().map_err(DiffError::parse(
+            "Failed to resolve merge keys in test file",
+        ))?;
+        serde_yaml::from_value(suite.clone()).map_err(|err| {
+            // Parse the cases again as the form the file looks like, since the
+            // untagged `Cases` hides why they failed to parse
+            let cases_error = if suite.get("groups").is_some() {
+                serde_yaml::from_value::<Groups>(suite).err()
+            } else {
+                serde_yaml::from_value::<Group>(suite).err()
+            };
+            DiffError::parse("Failed to parse test file")(cases_error.unwrap_or(err))
+        })
+    }
+
+    /// A suite with a case per subdirectory of `dir`, in name order, taking
+    /// its `args`, `input` and `expected` output from the files of these
+    /// names in it, each of which may be missing.
+    fn from_fixtures(dir: &Path) -> Result<Self, DiffError> {
+        let read_error = || DiffError::io(format!("Failed to read fixtures: {}", dir.display()));
+        let dir = std::fs::canonicalize(dir).map_err(read_error())?;
+        let mut fixtures = std::fs::read_dir(&dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<std::io::Result<Vec<_>>>()
+            })
+            .map_err(read_error())?;
+        fixtures.retain(|path| path.is_dir());
+        fixtures.sort();
+
+        let read = |path: &Path| match std::fs::read_to_string(path) {
+            Ok(text) => Ok(Some(text)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(DiffError::io(format!(
+                "Failed to read fixture file: {}",
+                path.display()
+            ))(err)),
+        };
+        let tests = fixtures
+            .iter()
+            .map(|fixture| {
+                let expected = fixture.join("expected");
+                Ok(TestCase {
+                    note: Some(
+                        fixture
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .into(),
+                    ),
+                    args: read(&fixture.join("args"))?.map(|args| args.trim().to_owned()),
+                    input: read(&fixture.join("input"))?,
+                    out_file: expected.is_file().then_some(expected),
+                    ..TestCase::default()
+                })
+            })
+            .collect::<Result<_, DiffError>>()?;
+        Ok(Self {
+            vars: BTreeMap::new(),
+            env_file: None,
+            out_file: None,
+            cases: Cases::Flat(Group { tests }),
+        })
+    }
+
+    /// Every case in file order, with the name of its group if the suite is
+    /// grouped.
+    fn cases(&self) -> impl Iterator<Item = (Option<&str>, &TestCase)> {
+        let (tests, groups) = match &self.cases {
+            Cases::Flat(group) => (group.tests.as_slice(), None),
+            Cases::Grouped(groups) => (&[][..], Some(&groups.groups)),
+        };
+        let grouped = groups
+            .into_iter()
+            .flatten()
+            .flat_map(|(name, group)| group.tests.iter().map(|case| (Some(name.as_str()), case)));
+        tests.iter().map(|case| (None, case)).chain(grouped)
+    }
+
+    /// Every case in the same order as `cases`.
+    fn cases_mut(&mut self) -> impl Iterator<Item = &mut TestCase> {
+        let (tests, groups) = match &mut self.cases {
+            Cases::Flat(group) => (group.tests.as_mut_slice(), None),
+            Cases::Grouped(groups) => (&mut [][..], Some(&mut groups.groups)),
+        };
+        let grouped = groups
+            .into_iter()
+            .flat_map(|groups| groups.values_mut())
+            .flat_map(|group| group.tests.iter_mut());
+        tests.iter_mut().chain(grouped)
+    }
+
+    fn has_group(&self, name: &str) -> bool {
+        matches!(&self.cases, Cases::Grouped(groups) if groups.groups.contains_key(name))
+    }
+}
+
+const STYLE_DELETED: Role = Role::Deleted;
+const STYLE_PASSED: Role = Role::Passed;
+const STYLE_INSERTED: Role = Role::Inserted;
+const STYLE_HINT: Role = Role::Hint;
+
+/// The order of the listed cases of a run.
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+enum SortOrder {
+    /// As in the test file, listing each case as soon as it and those before it finish
+    #[default]
+    Source,
+    /// By note
+    Name,
+    /// Slowest first
+    Time,
+    /// Failures first, then cases left unrun, then successes
+    Status,
+}
+
+impl SortOrder {
+    /// The indices of the cases in listing order, keeping source order among
+    /// equal ones.
+    fn order(self, cases: &[(usize, &TestCase)], outcomes: &[CaseOutcome]) -> Vec<usize> {
+        let mut order = (0..cases.len()).collect::<Vec<_>>();
+        match self {
+            Self::Source => {}
+            Self::Name => order.sort_by_key(|&index| cases[index].1.note()),
+            Self::Time => order.sort_by_key(|&index| std::cmp::Reverse(outcomes[index].duration)),
+            Self::Status => order.sort_by_key(|&index| {
+                let status = outcomes[index].status;
+                (!status.is_failure(), status.is_success())
+            }),
+        }
+        order
+    }
+}
+
+/// How failed cases are updated with their actual output.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Update {
+    /// Every failed case
+    All,
+    /// Only the cases confirmed on the terminal
+    Confirm,
+}
+
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct RunOptions {
+    /// Number of runs per case when benchmarking instead of comparing
+    bench_runs: Option<u32>,
+    /// Print a histogram of case run times after the run
+    timing_histogram: bool,
+    /// List failed, timed out and skipped cases by outcome after the summary
+    group_summary: bool,
+    /// Keep a progress line on stderr during the run
+    progress: bool,
+    /// Leave unknown placeholders in test cases as-is
+    allow_unresolved: bool,
+    /// Expand environment variables in args and input, failing on unset
+    /// ones if set to `Some(true)`
+    expand_env: Option<bool>,
+    /// Fail cases with invalid UTF-8 output instead of decoding it lossily
+    strict_utf8: bool,
+    /// Capture stderr along with stdout in every case
+    merge_stderr: bool,
+    /// Fail cases whose program exits with a non-zero status
+    require_success: bool,
+    /// Env file applied on top of the suite's `env_file`
+    env_file: Option<PathBuf>,
+    /// Applied to expected and actual outputs before comparing them
+    normalization: Normalization,
+    /// Only cases whose note contains this are run
+    filter: Option<String>,
+    /// How failing outputs are diffed
+    diff: DiffOptions,
+    /// Number of cases run in parallel
+    jobs: usize,
+    /// How often a program that failed to start for lack of resources is retried
+    spawn_retries: u32,
+    /// Print only the summary, not the individual cases
+    summary_only: bool,
+    /// Print a porcelain line per case instead of reports and the summary
+    porcelain: bool,
+    /// Show a char per finished case on stderr, listing only failed cases
+    /// after the run
+    dots: bool,
+    /// Print JSON-lines events instead of reports and the summary
+    events: bool,
+    /// How long the whole run may take before the remaining cases are left out
+    suite_timeout: Option<Duration>,
+    /// Order of the listed cases
+    sort: SortOrder,
+    /// File the summary is also written to
+    report: Option<PathBuf>,
+    /// Whether the `.json` report is indented
+    json_pretty: bool,
+    /// Only cases with these notes are run
+    rerun: Option<BTreeSet<String>>,
+    /// Only fixtures changed since this git revision are run
+    since: Option<String>,
+    /// Outcomes of an earlier run by note, to list status changes against
+    baseline: Option<BTreeMap<String, Outcome>>,
+    /// Only cases of this group are run
+    group: Option<String>,
+    /// Directory caching program outputs between runs
+    cache: Option<PathBuf>,
+    /// Whether to write actual outputs of failed cases back to the test file
+    update: Option<Update>,
+    /// Only update cases that regressed against the baseline
+    accept_regressions: bool,
+    /// JSON-lines file of per-case runtimes compared against and appended to
+    history: Option<PathBuf>,
+    /// Command line run for every failed case
+    on_failure: Option<String>,
+    /// Command line deciding whether outputs match instead of comparing them
+    equal_via: Option<String>,
+}
+
+impl RunOptions {
+    /// The options a run gets when no flag is given on the command line,
+    /// for subcommands that take none of the run flags.
+    fn cli_defaults() -> Self {
+        Self {
+            jobs: 1,
+            spawn_retries: DEFAULT_SPAWN_RETRIES,
+            ..Self::default()
+        }
+    }
+}
+
+/// The options of a run as they apply to one case.
+struct CaseOptions {
+    normalization: Normalization,
+    /// Whether a non-zero exit status fails the case
+    require_success: bool,
+}
+
+/// The cases picked for a run, each with the group it belongs to.
+type Selected<'a> = Vec<(Option<&'a str>, &'a TestCase)>;
+
+/// How many cases of the suite were left out of a run, and why.
+struct Selection<'a> {
+    total: usize,
+    /// Notes of the cases marked `skip`
+    skipped: Vec<&'a str>,
+    filtered: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Passed,
+    Failed,
+    /// A `should_fail` case that did not match, as expected
+    ExpectedFailure,
+    /// A `should_fail` case that matched after all
+    UnexpectedPass,
+    /// A `skip` case, which is not run
+    Skipped,
+    /// A case left unfinished at the `--suite-timeout` deadline
+    NotRun,
+}
+
+impl Outcome {
+    /// The outcome of a case whose output did or did not match.
+    fn new(matches: bool, should_fail: bool) -> Self {
+        match (matches, should_fail) {
+            (true, false) => Self::Passed,
+            (false, false) => Self::Failed,
+            (false, true) => Self::ExpectedFailure,
+            (true, true) => Self::UnexpectedPass,
+        }
+    }
+
+    /// Whether the outcome fails the run.
+    fn is_failure(self) -> bool {
+        matches!(self, Self::Failed | Self::UnexpectedPass)
+    }
+
+    /// Whether the case ran and did what was expected of it.
+    fn is_success(self) -> bool {
+        matches!(self, Self::Passed | Self::ExpectedFailure)
+    }
+
+    /// The style of the note of cases with this outcome.
+    fn style(self) -> anstyle::Style {
+        match self {
+            Self::Passed | Self::ExpectedFailure => STYLE_PASSED.style(),
+            Self::Failed | Self::UnexpectedPass => STYLE_DELETED.style(),
+            Self::Skipped | Self::NotRun => STYLE_INSERTED.style(),
+        }
+    }
+
+    /// The first column of `--porcelain` lines.
+    fn porcelain(self) -> &'static str {
+        match self {
+            Self::Passed => "PASS",
+            Self::Failed => "FAIL",
+            Self::ExpectedFailure => "XFAIL",
+            Self::UnexpectedPass => "XPASS",
+            Self::Skipped => "SKIP",
+            Self::NotRun => "NOTRUN",
+        }
+    }
+
+    /// The marker appended to the note of cases with this outcome.
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Passed | Self::Failed => "",
+            Self::ExpectedFailure => " (xfail)",
+            Self::UnexpectedPass => " (xpass)",
+            Self::Skipped => " (skipped)",
+            Self::NotRun => " (not run)",
+        }
+    }
+}
+
+struct CaseOutcome {
+    status: Outcome,
+    duration: Duration,
+    /// The program's output, if the case got as far as running it
+    actual_output: Option<String>,
+}
+
+/// How many of the outcomes are of each kind, like `3 passed, 1 failed`.
+fn outcome_counts(outcomes: &[&CaseOutcome]) -> String {
+    let count = |status| {
+        outcomes
+            .iter()
+            .filter(|outcome| outcome.status == status)
+            .count()
+    };
+    let mut counts = format!(
+        "{} passed, {} failed",
+        count(Outcome::Passed),
+        count(Outcome::Failed)
+    );
+    for (status, label) in [
+        (Outcome::ExpectedFailure, "xfail"),
+        (Outcome::UnexpectedPass, "xpass"),
+        (Outcome::NotRun, "not run"),
+    ] {
+        let count = count(status);
+        if count > 0 {
+            counts = format!("{counts}, {count} {label}");
+        }
+    }
+    counts
+}
+
+/// Writes a `--events` line right away, even when cases run in parallel.
+fn event(out: &mut impl Write, event: &serde_json::Value) -> Result<()> {
+    writeln!(out, "{event}")
+        .and_then(|()| out.flush())
+        .context("Failed to write event")
+}
+
+/// A `--porcelain` line, with tabs and line breaks in the note replaced by
+/// spaces so that it stays one line of three columns.
+fn porcelain_line(outcome: Outcome, duration: Duration, note: &str) -> String {
+    let note = note.replace(['\t', '\n', '\r'], " ");
+    format!(
+        "{}\t{}\t{note}\n",
+        outcome.porcelain(),
+        duration.as_millis()
+    )
+}
+
+/// The lines summarizing a run: how many cases were selected, if not all of
+/// them, how many of each group ended with each outcome, and the same for
+/// the whole run.
+fn summary(outcomes: &[CaseOutcome], groups: &[Option<&str>], selection: &Selection) -> String {
+    let mut lines = String::new();
+    if selection.skipped.len() + selection.filtered > 0 {
+        lines = format!(
+            "ran {}, skipped {}, filtered {} (of {})\n",
+            outcomes.len(),
+            selection.skipped.len(),
+            selection.filtered,
+            selection.total
+        );
+    }
+    for group in groups.iter().flatten().collect::<BTreeSet<_>>() {
+        let outcomes = outcomes
+            .iter()
+            .zip(groups)
+            .filter(|(_, case_group)| case_group.as_ref() == Some(group))
+            .map(|(outcome, _)| outcome)
+            .collect::<Vec<_>>();
+        lines.extend([format!("{group}: {}\n", outcome_counts(&outcomes))]);
+    }
+    let outcomes = outcomes.iter().collect::<Vec<_>>();
+    lines.extend([outcome_counts(&outcomes), "\n".to_owned()]);
+    lines
+}
+
+/// Whether the case succeeded in the baseline run and fails now.
+fn is_regression(baseline: &BTreeMap<String, Outcome>, note: &str, outcome: Outcome) -> bool {
+    baseline.get(note).is_some_and(|before| before.is_success()) && outcome.is_failure()
+}
+
+/// A `REGRESSION` line for every case that succeeded in the baseline run and
+/// fails now, and a `FIXED` line for every case that did the reverse.
+fn status_changes<'a>(
+    baseline: &BTreeMap<String, Outcome>,
+    results: impl Iterator<Item = (&'a str, Outcome)>,
+) -> String {
+    let mut lines = String::new();
+    for (note, outcome) in results {
+        let Some(&before) = baseline.get(note) else {
+            continue;
+        };
+        if is_regression(baseline, note, outcome) {
+            lines.extend([format!(
+                "{STYLE_DELETED}REGRESSION{STYLE_DELETED:#} {note}\n"
+            )]);
+        } else if before.is_failure() && outcome.is_success() {
+            lines.extend([format!("{STYLE_PASSED}FIXED{STYLE_PASSED:#} {note}\n")]);
+        }
+    }
+    lines
+}
+
+/// The notes of failed, timed out and skipped cases, each kind under its own
+/// heading, leaving out empty ones.
+fn grouped_notes<'a>(
+    results: impl Iterator<Item = (&'a str, Outcome)>,
+    skipped: &[&str],
+) -> String {
+    let (mut failures, mut timeouts) = (Vec::new(), Vec::new());
+    for (note, outcome) in results {
+        if outcome.is_failure() {
+            failures.push(note);
+        } else if outcome == Outcome::NotRun {
+            timeouts.push(note);
+        }
+    }
+    let mut lines = String::new();
+    for (heading, style, notes) in [
+        ("FAILURES", STYLE_DELETED, &failures[..]),
+        ("TIMEOUTS", STYLE_INSERTED, &timeouts[..]),
+        ("SKIPPED", STYLE_INSERTED, skipped),
+    ] {
+        if notes.is_empty() {
+            continue;
+        }
+        lines.extend([format!("{style}{heading}{style:#}\n")]);
+        lines.extend(notes.iter().map(|note| format!("  {note}\n")));
+    }
+    lines
+}
+
+const HISTOGRAM_BUCKETS: [(&str, Option<Duration>); 4] = [
+    ("<10ms", Some(Duration::from_millis(10))),
+    ("<100ms", Some(Duration::from_millis(100))),
+    ("<1s", Some(Duration::from_secs(1))),
+    (">=1s", None),
+];
+const HISTOGRAM_WIDTH: usize = 40;
+
+fn timing_histogram(outcomes: &[CaseOutcome]) -> [usize; HISTOGRAM_BUCKETS.len()] {
+    let mut counts = [0; HISTOGRAM_BUCKETS.len()];
+    for outcome in outcomes {
+        let bucket = HISTOGRAM_BUCKETS
+            .iter()
+            .position(|(_, limit)| limit.is_none_or(|limit| outcome.duration < limit))
+            .unwrap_or_default();
+        counts[bucket] += 1;
+    }
+    counts
+}
+
+fn print_timing_histogram(out: &mut impl Write, outcomes: &[CaseOutcome]) -> std::io::Result<()> {
+    let counts = timing_histogram(outcomes);
+    let max = counts.iter().copied().max().unwrap_or_default().max(1);
+    for ((label, _), count) in HISTOGRAM_BUCKETS.iter().zip(counts) {
+        let width = (count * HISTOGRAM_WIDTH).div_ceil(max);
+        writeln!(
+            out,
+            "{label:>6} {STYLE_INSERTED}{}{STYLE_INSERTED:#} {count}",
+            " ".repeat(width)
+        )?;
+    }
+    Ok(())
+}
+
+struct CaseTimings {
+    min: Duration,
+    median: Duration,
+    max: Duration,
+}
+
+impl CaseTimings {
+    fn from_samples(mut samples: Vec<Duration>) -> Self {
+        samples.sort_unstable();
+        Self {
+            min: samples[0],
+            median: samples[samples.len() / 2],
+            max: samples[samples.len() - 1],
+        }
+    }
+}
 
 struct TestRunner {
-    program_path: std::path::PathBuf,
+    program_path: PathBuf,
+    test_file: PathBuf,
     test_cases: TestSuite,
+    /// Variables loaded from env files for every spawned process
+    env: BTreeMap<String, String>,
+    options: RunOptions,
+    /// When `--suite-timeout` runs out
+    deadline: Option<Instant>,
+    /// Notes of the fixtures changed since the `--since` revision
+    changed: Option<BTreeSet<String>>,
 }
 
 impl TestRunner {
-    pub fn new(program_path: &str, test_file: &str) -> Result<Self> {
-        let program_path =
-            std::fs::canonicalize(program_path).context("Failed to resolve program path")?;
-        let test_file = std::fs::File::open(test_file).context("Failed to open test file")?;
-        let test_cases = serde_yaml::from_reader::<_, TestSuite>(test_file)
-            .context("Failed to parse test file")?;
+    pub fn new(
+        program_path: &str,
+        test_file: &str,
+        options: RunOptions,
+    ) -> Result<Self, DiffError> {
+        let program_path = std::fs::canonicalize(program_path)
+            .map_err(DiffError::io("Failed to resolve program path"))?;
+        let test_dir = Path::new(test_file).parent().unwrap_or(Path::new(""));
+        let test_cases = if Path::new(test_file).is_dir() {
+            TestSuite::from_fixtures(Path::new(test_file))?
+        } else {
+            TestSuite::load(test_file)?
+        };
+
+        let mut env = BTreeMap::new();
+        if let Some(env_file) = &test_cases.env_file {
+            env.extend(read_env_file(&test_dir.join(env_file))?);
+        }
+        if let Some(env_file) = &options.env_file {
+            env.extend(read_env_file(env_file)?);
+        }
+
+        let changed = options
+            .since
+            .as_deref()
+            .and_then(|rev| changed_fixtures(Path::new(test_file), rev));
 
         Ok(Self {
             program_path,
+            test_file: test_file.into(),
             test_cases,
+            env,
+            changed,
+            deadline: options
+                .suite_timeout
+                .map(|timeout| Instant::now() + timeout),
+            options,
         })
     }
 
-    pub fn run(&self) -> Result<()> {
-        for case in &self.test_cases.tests {
-            self.run_test_case(case)?;
+    /// Runs the selected cases, returning `ExitCode::Differs` if any of them
+    /// failed, or `ExitCode::Timeout` if the suite timeout left some unrun.
+    pub fn run(&self) -> Result<ExitCode> {
+        self.run_to(std::io::stdout())
+    }
+
+    /// Runs the selected cases like `run`, writing to `out`.
+    #[tracing::instrument(skip_all, fields(program = %self.program_path.display()))]
+    fn run_to(&self, mut out: impl Write + Send) -> Result<ExitCode> {
+        if let Some(runs) = self.options.bench_runs {
+            self.bench(&mut out, runs)?;
+            return Ok(ExitCode::Success);
         }
-        Ok(())
+        if let Some(group) = &self.options.group
+            && !self.test_cases.has_group(group)
+        {
+            anyhow::bail!("Test file has no group named {group:?}");
+        }
+        let (cases, selection) = self.select(&mut out)?;
+        let (groups, cases): (Vec<_>, Vec<_>) = cases.into_iter().unzip();
+        let cases = cases.into_iter().enumerate().collect::<Vec<_>>();
+        let mut history = self
+            .options
+            .history
+            .as_deref()
+            .map(History::load)
+            .transpose()?;
+        let progress = self.options.progress.then(|| {
+            let progress = Progress::new(cases.len(), self.options.jobs);
+            progress.draw();
+            progress
+        });
+        let output = Mutex::new((OrderedPrinter::new(out), progress));
+        let outcomes = parallel::parallel_map(&cases, self.options.jobs, |(index, case)| {
+            let result = self.run_selected(&output, case, history.as_ref());
+            self.show_result(&output, *index, case, &result)?;
+            if let (Some(hook), Ok((outcome, report))) = (&self.options.on_failure, &result)
+                && outcome.status.is_failure()
+            {
+                self.run_failure_hook(case, report, hook)?;
+            }
+            result
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+        let (printer, _) = output.into_inner().unwrap_or_else(PoisonError::into_inner);
+        let mut out = printer.into_inner();
+        if self.options.progress {
+            Progress::clear();
+        }
+        if self.options.dots {
+            eprintln!();
+        }
+        let (outcomes, reports): (Vec<_>, Vec<_>) = outcomes.into_iter().unzip();
+        if let Some(history) = &mut history {
+            let durations = cases
+                .iter()
+                .zip(&outcomes)
+                .filter(|(_, outcome)| outcome.actual_output.is_some())
+                .map(|((_, case), outcome)| (case.note(), outcome.duration));
+            history.record(durations)?;
+        }
+        let order = self.options.sort.order(&cases, &outcomes);
+        if self.options.dots {
+            for &index in order
+                .iter()
+                .filter(|&&index| outcomes[index].status.is_failure())
+            {
+                write!(out, "{}", reports[index])?;
+            }
+        } else if self.lists_cases() && self.options.sort != SortOrder::Source {
+            for &index in &order {
+                write!(
+                    out,
+                    "{}",
+                    self.listing(cases[index].1, &outcomes[index], &reports[index])
+                )?;
+            }
+        }
+        self.write_summary(&mut out, &cases, &groups, &outcomes, &selection, &order)?;
+        if self.options.timing_histogram {
+            print_timing_histogram(&mut out, &outcomes)?;
+        }
+        if let Some(update) = self.options.update {
+            let cases = cases.iter().map(|&(_, case)| case);
+            let results =
+                cases
+                    .zip(outcomes.iter().zip(&reports))
+                    .filter(|(case, (outcome, _))| {
+                        !self.options.accept_regressions
+                            || self.options.baseline.as_ref().is_some_and(|baseline| {
+                                is_regression(baseline, case.note(), outcome.status)
+                            })
+                    });
+            self.update(&mut out, results, update, prompt)?;
+        }
+        if let Some(timeout) = self.options.suite_timeout
+            && outcomes
+                .iter()
+                .any(|outcome| outcome.status == Outcome::NotRun)
+        {
+            eprintln!(
+                "Suite timeout of {} reached",
+                humantime::format_duration(timeout)
+            );
+            return Ok(ExitCode::Timeout);
+        }
+        let failed = outcomes.iter().any(|outcome| outcome.status.is_failure());
+        Ok(ExitCode::differs(failed))
     }
 
-    fn run_test_case(&self, case: &TestCase) -> Result<()> {
-        let mut command = Command::new(&self.program_path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .args(case.args.as_deref().unwrap_or_default().split_whitespace())
-            .spawn()
-            .context("Failed to start program")?;
+    /// Lists a finished case, if cases are listed as they finish, and updates
+    /// the progress line.
+    fn show_result(
+        &self,
+        output: &Mutex<(OrderedPrinter<impl Write>, Option<Progress>)>,
+        index: usize,
+        case: &TestCase,
+        result: &Result<(CaseOutcome, String)>,
+    ) -> Result<()> {
+        // Failed cases still push a block so later ones are not held back
+        let report = result
+            .as_ref()
+            .ok()
+            .filter(|_| {
+                self.lists_cases() && self.options.sort == SortOrder::Source && !self.options.dots
+            })
+            .map(|(outcome, report)| self.listing(case, outcome, report));
+        let mut output = output.lock().unwrap_or_else(PoisonError::into_inner);
+        let (printer, progress) = &mut *output;
+        if progress.is_some() {
+            Progress::clear();
+        }
+        printer.push(index, report.unwrap_or_default())?;
+        if self.options.dots
+            && let Ok((outcome, _)) = result
+        {
+            eprint!("{}", dot(outcome.status));
+            let _ = std::io::stderr().flush();
+        }
+        if let Some(progress) = progress {
+            let duration = result
+                .as_ref()
+                .map_or(Duration::ZERO, |(outcome, _)| outcome.duration);
+            progress.finish(duration);
+            progress.draw();
+        }
+        Ok(())
+    }
 
-        if let Some(input) = &case.input {
-            command
-                .stdin
-                .as_mut()
-                .ok_or_else(|| anyhow::anyhow!("Failed to get stdin"))?
-                .write_all(input.as_bytes())
-                .context("Failed to write input to program")?;
+    /// Runs a selected case unless the suite timeout has passed, emitting its
+    /// events.
+    fn run_selected(
+        &self,
+        output: &Mutex<(OrderedPrinter<impl Write>, Option<Progress>)>,
+        case: &TestCase,
+        history: Option<&History>,
+    ) -> Result<(CaseOutcome, String)> {
+        // Events bypass the printer's ordering, so they show up right away
+        let events = || output.lock().unwrap_or_else(PoisonError::into_inner);
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            let result = not_run_case(case);
+            self.finish_event(events().0.get_mut(), case, &result.0)?;
+            return Ok(result);
+        }
+        if self.options.events {
+            event(
+                events().0.get_mut(),
+                &serde_json::json!({"event": "start", "note": case.note()}),
+            )?;
         }
+        let result = self
+            .resolve(case)
+            .and_then(|case| self.run_test_case(&case, history));
+        let result = match result {
+            Err(err) if matches!(err.downcast_ref(), Some(DiffError::Timeout { .. })) => {
+                not_run_case(case)
+            }
+            result => result?,
+        };
+        self.finish_event(events().0.get_mut(), case, &result.0)?;
+        Ok(result)
+    }
 
-        let output = command
-            .wait_with_output()
-            .context("Failed to get program output")?;
+    fn finish_event(
+        &self,
+        out: &mut impl Write,
+        case: &TestCase,
+        outcome: &CaseOutcome,
+    ) -> Result<()> {
+        if !self.options.events {
+            return Ok(());
+        }
+        event(
+            out,
+            &serde_json::json!({
+                "event": "finish",
+                "note": case.note(),
+                "status": outcome.status,
+                "ms": u64::try_from(outcome.duration.as_millis()).unwrap_or(u64::MAX),
+            }),
+        )
+    }
 
-        let expected_output = case.out.as_deref().unwrap_or_default();
-        let actual_output = String::from_utf8_lossy(&output.stdout);
+    /// Whether cases are listed one by one, as reports or porcelain lines.
+    fn lists_cases(&self) -> bool {
+        !self.options.summary_only && !self.options.events
+    }
 
-        if expected_output == actual_output {
-            println!(
-                "{STYLE_GREEN}{}{STYLE_GREEN:#}\n{actual_output}",
-                case.note.as_deref().unwrap_or("Unnamed test case")
-            );
+    /// How a finished case is listed: its report, or its porcelain line.
+    fn listing(&self, case: &TestCase, outcome: &CaseOutcome, report: &str) -> String {
+        if self.options.porcelain {
+            porcelain_line(outcome.status, outcome.duration, case.note())
         } else {
-            let diff = compute_diff(expected_output, &actual_output)?;
+            report.to_owned()
+        }
+    }
 
-            println!(
-                "{STYLE_RED}{}{STYLE_RED:#}\n{diff}",
-                case.note.as_deref().unwrap_or("Unnamed test case")
-            );
+    /// Prints the summary and the status changes against the baseline, and
+    /// writes the report, listing the cases in `order`.
+    fn write_summary(
+        &self,
+        out: &mut impl Write,
+        cases: &[(usize, &TestCase)],
+        groups: &[Option<&str>],
+        outcomes: &[CaseOutcome],
+        selection: &Selection,
+        order: &[usize],
+    ) -> Result<()> {
+        let summary = summary(outcomes, groups, selection);
+        let results = || {
+            order
+                .iter()
+                .map(|&index| (cases[index].1, &outcomes[index]))
+        };
+        if !self.options.porcelain && !self.options.events {
+            write!(out, "{summary}")?;
+            if let Some(baseline) = &self.options.baseline {
+                let results = results().map(|(case, outcome)| (case.note(), outcome.status));
+                write!(out, "{}", status_changes(baseline, results))?;
+            }
+            if self.options.group_summary {
+                let results = results().map(|(case, outcome)| (case.note(), outcome.status));
+                write!(out, "{}", grouped_notes(results, &selection.skipped))?;
+            }
         }
+        if let Some(path) = &self.options.report {
+            let report = if path.extension() == Some(OsStr::new("json")) {
+                let results = results()
+                    .map(|(case, outcome)| (case.note(), outcome.status, outcome.duration));
+                to_json(&Report::new(results), self.options.json_pretty)? + "\n"
+            } else {
+                summary
+            };
+            std::fs::write(path, report).context("Failed to write report")?;
+        }
+        Ok(())
+    }
 
+    /// Rewrites the test file with the actual outputs of failed cases as their
+    /// `out`, asking `ask` before each one when `update` is `Update::Confirm`.
+    ///
+    /// Cases whose expected output comes from `out_any`, `out_contains`,
+    /// `out_prefix`, `out_suffix`, `ref_command` or an `out_file`, or that are
+    /// expected to fail, are left alone.
+    fn update<'a>(
+        &self,
+        out: &mut impl Write,
+        results: impl Iterator<Item = (&'a TestCase, (&'a CaseOutcome, &'a String))>,
+        mut update: Update,
+        mut ask: impl FnMut(&str) -> Result<String>,
+    ) -> Result<()> {
+        let mut suite = self.test_cases.clone();
+        let mut updated = 0;
+        for (case, (outcome, report)) in results {
+            let Some(actual_output) = &outcome.actual_output else {
+                continue;
+            };
+            let from_file = case.out.is_none()
+                && (case.out_file.is_some() || self.test_cases.out_file.is_some());
+            if outcome.status != Outcome::Failed
+                || !case.out_any.is_empty()
+                || case.is_partial()
+                || case.ref_command.is_some()
+                || from_file
+            {
+                continue;
+            }
+            if update == Update::Confirm {
+                write!(out, "{report}")?;
+                out.flush()?;
+                match ask("update? [y/N/a/q] ")?.as_str() {
+                    "y" => {}
+                    "a" => update = Update::All,
+                    "q" => break,
+                    _ => continue,
+                }
+            }
+            let index = self
+                .test_cases
+                .cases()
+                .position(|(_, test)| std::ptr::eq(test, case))
+                .expect("selected cases come from the suite");
+            let test = suite
+                .cases_mut()
+                .nth(index)
+                .expect("the copy has every case");
+            test.out = Some(actual_output.clone());
+            updated += 1;
+        }
+        if updated > 0 {
+            let yaml = serde_yaml::to_string(&suite)?;
+            std::fs::write(&self.test_file, yaml).context("Failed to write test file")?;
+        }
+        writeln!(
+            out,
+            "Updated {updated} case(s) in {}",
+            self.test_file.display()
+        )?;
         Ok(())
     }
-}
 
-fn read_file(path: &str) -> Result<String> {
-    std::fs::read_to_string(path).context(format!("Failed to read file: {path}"))
-}
+    /// Picks the cases to run along with their groups, listing the skipped
+    /// ones in `out`.
+    fn select(&self, out: &mut impl Write) -> Result<(Selected<'_>, Selection<'_>)> {
+        let tests = self.test_cases.cases().collect::<Vec<_>>();
+        if let Some(rerun) = &self.options.rerun {
+            let missing = rerun
+                .iter()
+                .filter(|&note| tests.iter().all(|(_, case)| case.note() != note))
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                eprintln!(
+                    "Report lists cases missing from the test file: {}",
+                    missing.join(", ")
+                );
+            }
+        }
+        let matching = tests
+            .iter()
+            .filter(|(group, _)| {
+                self.options
+                    .group
+                    .as_deref()
+                    .is_none_or(|selected| *group == Some(selected))
+            })
+            .filter(|(_, case)| {
+                self.options
+                    .filter
+                    .as_ref()
+                    .is_none_or(|filter| case.note.as_deref().unwrap_or_default().contains(filter))
+            })
+            .filter(|(_, case)| {
+                self.options
+                    .rerun
+                    .as_ref()
+                    .is_none_or(|rerun| rerun.contains(case.note()))
+            })
+            .filter(|(_, case)| {
+                self.changed
+                    .as_ref()
+                    .is_none_or(|changed| changed.contains(case.note()))
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        let (skipped, cases): (Vec<_>, Vec<_>) =
+            matching.into_iter().partition(|(_, case)| case.skip);
+        for (_, case) in skipped.iter().filter(|_| !self.options.summary_only) {
+            if self.options.events {
+                let outcome = CaseOutcome {
+                    status: Outcome::Skipped,
+                    duration: Duration::ZERO,
+                    actual_output: None,
+                };
+                self.finish_event(out, case, &outcome)?;
+            } else if self.options.porcelain {
+                write!(
+                    out,
+                    "{}",
+                    porcelain_line(Outcome::Skipped, Duration::ZERO, case.note())
+                )?;
+            } else if self.options.dots {
+                eprint!("{}", dot(Outcome::Skipped));
+            } else {
+                writeln!(out, "{}", case_header(Outcome::Skipped, case.note()))?;
+            }
+        }
+        let selection = Selection {
+            total: tests.len(),
+            skipped: skipped.iter().map(|(_, case)| case.note()).collect(),
+            filtered: tests.len() - skipped.len() - cases.len(),
+        };
+        Ok((cases, selection))
+    }
 
-fn compute_diff(left: &str, right: &str) -> Result<DiffVec> {
-    let dmp = DiffMatchPatch::new();
-    dmp.diff_main::<Compat>(left, right)
-        .map(DiffVec)
-        .map_err(|e| anyhow::anyhow!("Diff computation failed: {e:?}"))
-}
+    fn bench(&self, out: &mut impl Write, runs: u32) -> Result<()> {
+        let started = Instant::now();
+        let (cases, _) = self.select(out)?;
+        for &(_, case) in &cases {
+            let case = self.with_generated_input(&self.resolve(case)?)?;
+            let timings = self.bench_test_case(&case, runs)?;
+            writeln!(
+                out,
+                "{STYLE_INSERTED}{}{STYLE_INSERTED:#}\nmin {:?}  median {:?}  max {:?}",
+                case.note(),
+                timings.min,
+                timings.median,
+                timings.max
+            )?;
+        }
+        writeln!(
+            out,
+            "Total: {:?} for {} runs",
+            started.elapsed(),
+            cases.len() * runs as usize
+        )?;
+        Ok(())
+    }
 
-fn files_diff(left: &str, right: &str) -> Result<DiffVec> {
-    compute_diff(&read_file(left)?, &read_file(right)?)
-}
+    /// Substitutes `{{NAME}}` placeholders in the case from the suite `vars`
+    /// or, failing that, the environment.
+    fn resolve(&self, case: &TestCase) -> Result<TestCase> {
+        let substitute = |text: &str| {
+            substitute_placeholders(text, &self.test_cases.vars, self.options.allow_unresolved)
+        };
+        let substitute_option = |text: &Option<String>| text.as_deref().map(substitute).transpose();
+        let expand = |text: &str| -> Result<String> {
+            let text = substitute(text)?;
+            match self.options.expand_env {
+                Some(strict) => expand_env(&text, strict),
+                None => Ok(text),
+            }
+        };
+        let expand_option = |text: &Option<String>| text.as_deref().map(expand).transpose();
+        Ok(TestCase {
+            args: expand_option(&case.args)?,
+            input: expand_option(&case.input)?,
+            inputs: case
+                .inputs
+                .iter()
+                .map(|input| expand(input))
+                .collect::<Result<_>>()?,
+            input_command: substitute_option(&case.input_command)?,
+            cwd: expand_option(&case.cwd)?,
+            out: substitute_option(&case.out)?,
+            out_any: case
+                .out_any
+                .iter()
+                .map(|out| substitute(out))
+                .collect::<Result<_>>()?,
+            out_contains: case
+                .out_contains
+                .iter()
+                .map(|out| substitute(out))
+                .collect::<Result<_>>()?,
+            out_prefix: substitute_option(&case.out_prefix)?,
+            out_suffix: substitute_option(&case.out_suffix)?,
+            ..case.clone()
+        })
+    }
 
-struct DiffVec(Vec<crate::dmp::Diff<char>>);
+    fn bench_test_case(&self, case: &TestCase, runs: u32) -> Result<CaseTimings> {
+        let samples = (0..runs)
+            .map(|_| {
+                let started = Instant::now();
+                self.execute(case)?;
+                Ok(started.elapsed())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(CaseTimings::from_samples(samples))
+    }
 
-impl std::fmt::Display for DiffVec {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for diff in &self.0 {
-            let text = diff.data().iter().copied().collect::<String>();
-            match diff.op() {
-                Ops::Delete => write!(f, "{STYLE_RED}{text}{STYLE_RED:#}"),
-                Ops::Equal => write!(f, "{text}"),
-                Ops::Insert => write!(f, "{STYLE_CYAN}{text}{STYLE_CYAN:#}"),
-            }?;
+    /// The run's options as they apply to the case, with its own fields
+    /// taking precedence.
+    fn case_options(&self, case: &TestCase) -> CaseOptions {
+        let global = &self.options.normalization;
+        CaseOptions {
+            normalization: Normalization {
+                trim: case.trim.unwrap_or(global.trim),
+                collapse_spaces: case.collapse_spaces.unwrap_or(global.collapse_spaces),
+                normalize_paths: case.normalize_paths.unwrap_or(global.normalize_paths),
+                ignore_case: case.ignore_case.unwrap_or(global.ignore_case),
+                tabsize: case.tabsize.map_or(global.tabsize, NonZeroUsize::new),
+                ..global.clone()
+            },
+            require_success: self.options.require_success && !case.allow_nonzero_exit,
+        }
+    }
+
+    /// The environment of a case's child processes: the env files overlaid
+    /// with the case's own `env`.
+    fn case_env(&self, case: &TestCase) -> BTreeMap<String, String> {
+        let mut env = self.env.clone();
+        env.extend(case.env.clone());
+        env
+    }
+
+    /// The directory the case's commands run in, if it sets one.
+    fn case_dir(&self, case: &TestCase) -> Option<PathBuf> {
+        let test_dir = self.test_file.parent().unwrap_or(Path::new(""));
+        case.cwd.as_deref().map(|cwd| test_dir.join(cwd))
+    }
+
+    fn execute(&self, case: &TestCase) -> Result<Output, DiffError> {
+        self.execute_program(&self.program_path, case)
+    }
+
+    /// Runs `program` with the case's arguments, input and environment.
+    fn execute_program(&self, program: &Path, case: &TestCase) -> Result<Output, DiffError> {
+        spawn_program(
+            program,
+            case.args.as_deref().unwrap_or_default(),
+            &case.stdin(),
+            &self.case_env(case),
+            self.case_dir(case).as_deref(),
+            self.deadline,
+            case.merge_stderr || self.options.merge_stderr,
+            self.options.spawn_retries,
+        )
+    }
+
+    /// The program's stdout for the case and its exit status, reused from
+    /// the cache directory when the program, arguments, input and environment
+    /// are unchanged.
+    ///
+    /// Only outputs of successful runs are cached, so reused ones come
+    /// without a status.
+    fn cached_stdout(&self, case: &TestCase) -> Result<(Vec<u8>, Option<ExitStatus>)> {
+        let Some(cache) = &self.options.cache else {
+            let output = self.execute(case)?;
+            return Ok((output.stdout, Some(output.status)));
+        };
+        let modified = std::fs::metadata(&self.program_path)
+            .and_then(|metadata| metadata.modified())
+            .context("Failed to read program modification time")?;
+        // The key must not change between builds, which rules out `Hash`
+        let key = serde_json::json!({
+            "program": self.program_path,
+            "modified": modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string(),
+            "args": case.args,
+            "input": case.input,
+            "inputs": case.inputs,
+            "merge_stderr": case.merge_stderr || self.options.merge_stderr,
+            "env": self.case_env(case),
+            "cwd": self.case_dir(case),
+        });
+        let digest = sha2::Sha256::digest(key.to_string());
+        let name = digest.iter().fold(String::new(), |mut name, byte| {
+            let _ = std::fmt::Write::write_fmt(&mut name, format_args!("{byte:02x}"));
+            name
+        });
+        let path = cache.join(format!("{name}.out"));
+
+        if let Ok(stdout) = std::fs::read(&path) {
+            tracing::debug!(path = %path.display(), "reusing cached output");
+            return Ok((stdout, None));
+        }
+        let output = self.execute(case)?;
+        if output.status.success() {
+            std::fs::create_dir_all(cache).context("Failed to create cache directory")?;
+            std::fs::write(&path, &output.stdout).context("Failed to write cached output")?;
+        }
+        Ok((output.stdout, Some(output.status)))
+    }
+
+    /// Runs a helper command line with the case's input and environment.
+    fn run_command(&self, case: &TestCase, command: &str) -> Result<Output, DiffError> {
+        let command = command.trim();
+        let (program, args) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        spawn_program(
+            program,
+            args,
+            &case.stdin(),
+            &self.case_env(case),
+            self.case_dir(case).as_deref(),
+            self.deadline,
+            false,
+            self.options.spawn_retries,
+        )
+    }
+
+    /// Runs the `--on-failure` command for a failed case, passing its report,
+    /// styled as printed, on stdin.
+    fn run_failure_hook(&self, case: &TestCase, report: &str, hook: &str) -> Result<()> {
+        let hook = hook.trim();
+        let (program, args) = hook.split_once(char::is_whitespace).unwrap_or((hook, ""));
+        let mut env = self.case_env(case);
+        env.insert("DIFF_CASE_NOTE".to_owned(), case.note().to_owned());
+        let output = spawn_program(
+            program,
+            args,
+            &[report],
+            &env,
+            None,
+            None,
+            false,
+            self.options.spawn_retries,
+        )
+        .context("Failed to run --on-failure command")?;
+        if !output.status.success() {
+            eprintln!(
+                "--on-failure command for {:?} exited with {}",
+                case.note(),
+                output.status
+            );
         }
         Ok(())
     }
-}
 
-const EXAMPLE_STRING: &str = r"
- - note: test
-   args: arguments
-   input: input
-   out: output
- - note: test
-   args: arguments
-   input: input
-   out: output
- - note: test
-   args: arguments
-   input: input
-   out: output
-";
+    /// Whether the outputs match, as decided by the `--equal-via` command if
+    /// there is one.
+    fn outputs_match(&self, case: &TestCase, expected: &str, actual: &str) -> Result<bool> {
+        let Some(command) = &self.options.equal_via else {
+            return Ok(expected == actual);
+        };
+        let command = command.trim();
+        let (program, args) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let output = spawn_program(
+            program,
+            args,
+            &[&format!("{expected}\0{actual}")],
+            &self.case_env(case),
+            None,
+            self.deadline,
+            false,
+            self.options.spawn_retries,
+        )
+        .context("Failed to run --equal-via command")?;
+        Ok(output.status.success())
+    }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+    fn reference_output(&self, case: &TestCase, ref_command: &str) -> Result<String> {
+        let output = self
+            .run_command(case, ref_command)
+            .context("Failed to run reference command")?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
 
-    match cli.command {
-        Commands::Diff { left, right, mode } => match mode {
-            Mode::Program => {
-                TestRunner::new(&left, &right)?.run()?;
+    /// The output of the case's `input_command`, if it has one.
+    fn generate_input(&self, case: &TestCase) -> Result<Option<String>> {
+        if !case.inputs.is_empty() {
+            for (field, set) in [
+                ("input", case.input.is_some()),
+                ("input_command", case.input_command.is_some()),
+            ] {
+                if set {
+                    anyhow::bail!("Test case sets both inputs and {field}");
+                }
             }
-            Mode::Interactive => println!("{}", compute_diff(&left, &right)?),
-            Mode::File => println!("{}", files_diff(&left, &right)?),
-        },
-        Commands::Example => {
-            println!("{EXAMPLE_STRING}");
+            return Ok(None);
+        }
+        let Some(input_command) = &case.input_command else {
+            return Ok(None);
+        };
+        if case.input.is_some() {
+            anyhow::bail!("Test case sets both input and input_command");
+        }
+        let output = self
+            .run_command(case, input_command)
+            .context("Failed to run input command")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Input command {input_command:?} failed with {}",
+                output.status
+            );
+        }
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+
+    /// The case's `out`, or else the contents of its own or the suite's
+    /// `out_file`.
+    fn expected_output(&self, case: &TestCase) -> Result<String> {
+        if let Some(out) = &case.out {
+            return Ok(out.clone());
+        }
+        let Some(out_file) = case.out_file.as_ref().or(self.test_cases.out_file.as_ref()) else {
+            return Ok(String::new());
+        };
+        let test_dir = self.test_file.parent().unwrap_or(Path::new(""));
+        let path = test_dir.join(out_file);
+        std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read expected output: {}", path.display()))
+    }
+
+    /// The case with its `input_command` replaced by the input it makes, and
+    /// its `inputs` ended with newlines.
+    fn with_generated_input(&self, case: &TestCase) -> Result<TestCase> {
+        let inputs = case.inputs.iter().map(|input| {
+            if input.ends_with('\n') {
+                input.clone()
+            } else {
+                format!("{input}\n")
+            }
+        });
+        Ok(TestCase {
+            input: self.generate_input(case)?.or_else(|| case.input.clone()),
+            inputs: inputs.collect(),
+            input_command: None,
+            ..case.clone()
+        })
+    }
+
+    /// Runs the case, returning its outcome along with the report to print.
+    #[tracing::instrument(skip_all, fields(note = case.note.as_deref().unwrap_or_default()))]
+    fn run_test_case(
+        &self,
+        case: &TestCase,
+        history: Option<&History>,
+    ) -> Result<(CaseOutcome, String)> {
+        if case.out.is_some() && !case.out_any.is_empty() {
+            anyhow::bail!("Test case {:?} sets both out and out_any", case.note());
+        }
+        for (field, set) in [
+            ("out_contains", !case.out_contains.is_empty()),
+            ("out_prefix", case.out_prefix.is_some()),
+            ("out_suffix", case.out_suffix.is_some()),
+        ] {
+            if case.out.is_some() && set {
+                anyhow::bail!("Test case {:?} sets both out and {field}", case.note());
+            }
+        }
+        let case = &match self.with_generated_input(case) {
+            Ok(case) => case,
+            Err(err) => {
+                return Ok(failed_case(case, Duration::ZERO, &format!("{err:#}")));
+            }
+        };
+        let (outcome, mut report) = self.check_output(case, history)?;
+        if outcome.status.is_failure() {
+            // Keep the blank line that separates reports after the command
+            if report.ends_with("\n\n") {
+                report.pop();
+            }
+            let repro = self.repro_command(case);
+            report.extend([format!("{STYLE_HINT}repro: {repro}{STYLE_HINT:#}\n\n")]);
+        }
+        Ok((outcome, report))
+    }
+
+    /// Runs a case whose input is generated and compares its output with
+    /// what the case expects.
+    fn check_output(
+        &self,
+        case: &TestCase,
+        history: Option<&History>,
+    ) -> Result<(CaseOutcome, String)> {
+        let options = self.case_options(case);
+        let normalization = &options.normalization;
+
+        let started = Instant::now();
+        let (stdout, exit_status) = self.cached_stdout(case)?;
+        let duration = started.elapsed();
+        if let Some(exit_status) = exit_status
+            && !exit_status.success()
+            && options.require_success
+        {
+            let reason = format!("Program exited with {exit_status}");
+            return Ok(failed_case(case, duration, &reason));
+        }
+
+        let actual_output = if self.options.strict_utf8 {
+            match std::str::from_utf8(&stdout) {
+                Ok(stdout) => normalization.apply(stdout),
+                Err(err) => {
+                    let reason = format!("Output is not valid UTF-8 at byte {}", err.valid_up_to());
+                    return Ok(failed_case(case, duration, &reason));
+                }
+            }
+        } else {
+            normalization.apply(&String::from_utf8_lossy(&stdout))
+        };
+        let note = match history {
+            Some(history) => timed_note(case.note(), duration, history.average(case.note())),
+            None => case.note().to_owned(),
+        };
+        let (status, report) = if case.is_partial() {
+            check_partial(case, &note, &actual_output)
+        } else {
+            let expected_outputs = match &case.ref_command {
+                Some(ref_command) => vec![self.reference_output(case, ref_command)?],
+                None if !case.out_any.is_empty() => case.out_any.clone(),
+                None => vec![self.expected_output(case)?],
+            };
+            let expected_outputs = expected_outputs
+                .iter()
+                .map(|expected| normalization.apply(expected))
+                .collect::<Vec<_>>();
+            let mut accepted = None;
+            for expected in &expected_outputs {
+                if self.outputs_match(case, expected, &actual_output)? {
+                    accepted = Some(expected.clone());
+                    break;
+                }
+            }
+            // Only the diff of a failed case shows the closest candidate
+            let (expected_output, matches) = match accepted {
+                Some(expected) => (expected, true),
+                None => (
+                    closest_match(expected_outputs.into_iter(), &actual_output)?,
+                    false,
+                ),
+            };
+            compare_outputs(
+                case,
+                &note,
+                &expected_output,
+                &actual_output,
+                matches,
+                self.options.diff,
+            )?
+        };
+        tracing::info!(
+            ?duration,
+            passed = status == Outcome::Passed,
+            "case finished"
+        );
+
+        let outcome = CaseOutcome {
+            status,
+            duration,
+            actual_output: Some(String::from_utf8_lossy(&stdout).into_owned()),
+        };
+        Ok((outcome, report))
+    }
+
+    /// A shell command line running the program like the case does, for
+    /// debugging a failure outside the harness.
+    fn repro_command(&self, case: &TestCase) -> String {
+        let mut words = Vec::new();
+        let env = self.case_env(case);
+        if !env.is_empty() {
+            words.push("env".to_owned());
+            words.extend(
+                env.iter()
+                    .map(|(key, value)| shell_quote(&format!("{key}={value}"))),
+            );
+        }
+        words.push(shell_quote(&self.program_path.display().to_string()));
+        let args = case.args.as_deref().unwrap_or_default();
+        words.extend(args.split_whitespace().map(shell_quote));
+        if case.merge_stderr || self.options.merge_stderr {
+            words.push("2>&1".to_owned());
+        }
+        let command = words.join(" ");
+        match &case.input {
+            Some(input) => format!("printf '%s' {} | {command}", shell_quote(input)),
+            None => format!("{command} < /dev/null"),
+        }
+    }
+
+    /// Runs every case against both the reference program and `candidate`,
+    /// expecting identical outputs instead of the cases' `out`, and ends with
+    /// a summary. Returns `ExitCode::Differs` if any outputs differ.
+    pub fn run_differential(&self, out: &mut impl Write, candidate: &str) -> Result<ExitCode> {
+        let candidate =
+            std::fs::canonicalize(candidate).context("Failed to resolve candidate path")?;
+        let (selected, _) = self.select(out)?;
+        let mut outcomes = Vec::new();
+        for (_, case) in selected {
+            let start = Instant::now();
+            let case = self.with_generated_input(&self.resolve(case)?)?;
+            let reference_output = self.execute(&case)?;
+            let candidate_output = self.execute_program(&candidate, &case)?;
+            let reference_output = String::from_utf8_lossy(&reference_output.stdout);
+            let candidate_output = String::from_utf8_lossy(&candidate_output.stdout);
+            let matches = self.outputs_match(&case, &reference_output, &candidate_output)?;
+            let (status, report) = compare_outputs(
+                &case,
+                case.note(),
+                &reference_output,
+                &candidate_output,
+                matches,
+                self.options.diff,
+            )?;
+            write!(out, "{report}")?;
+            outcomes.push(CaseOutcome {
+                status,
+                duration: start.elapsed(),
+                actual_output: Some(candidate_output.into_owned()),
+            });
+        }
+        writeln!(
+            out,
+            "{}",
+            outcome_counts(&outcomes.iter().collect::<Vec<_>>())
+        )?;
+        let failed = outcomes.iter().any(|outcome| outcome.status.is_failure());
+        Ok(ExitCode::differs(failed))
+    }
+}
+
+/// Picks the candidate equal to `actual`, or else the one with the smallest
+/// edit distance to it.
+fn closest_match(
+    candidates: impl Iterator<Item = String>,
+    actual: &str,
+) -> Result<String, DiffError> {
+    let mut closest = None;
+    for candidate in candidates {
+        if candidate == actual {
+            return Ok(candidate);
+        }
+        let distance = compute_diff(&candidate, actual)?.distance();
+        if closest.as_ref().is_none_or(|&(best, _)| distance < best) {
+            closest = Some((distance, candidate));
+        }
+    }
+    Ok(closest.map(|(_, candidate)| candidate).unwrap_or_default())
+}
+
+/// Renders `note`, green if the case passed and red otherwise,
+/// followed by the output if both outputs match or their diff if not.
+fn compare_outputs(
+    case: &TestCase,
+    note: &str,
+    expected_output: &str,
+    actual_output: &str,
+    matches: bool,
+    diff_options: DiffOptions,
+) -> Result<(Outcome, String)> {
+    let status = Outcome::new(matches, case.should_fail);
+    let header = case_header(status, note);
+
+    let report = if matches {
+        format!("{header}\n{actual_output}\n")
+    } else {
+        let diff = diff_options.diff(expected_output, actual_output)?;
+
+        format!("{header}\n{diff}\n")
+    };
+    Ok((status, report))
+}
+
+/// Checks that the output contains every `out_contains` substring and
+/// starts with `out_prefix` and ends with `out_suffix`, listing the missing
+/// substrings and where the ends stop matching below the output otherwise.
+fn check_partial(case: &TestCase, note: &str, actual_output: &str) -> (Outcome, String) {
+    let mut problems = case
+        .out_contains
+        .iter()
+        .filter(|substring| !actual_output.contains(substring.as_str()))
+        .map(|substring| format!("{STYLE_DELETED}missing:{STYLE_DELETED:#} {substring:?}\n"))
+        .collect::<Vec<_>>();
+    if let Some(prefix) = &case.out_prefix
+        && let Some(at) = mismatch(prefix.chars(), actual_output.chars())
+    {
+        let rest = |text: &str| {
+            text.chars()
+                .skip(at)
+                .take(FIRST_DIFF_CONTEXT)
+                .collect::<String>()
+        };
+        problems.push(format!(
+            "{STYLE_DELETED}prefix differs at char {}:{STYLE_DELETED:#} expected {:?}, got {:?}\n",
+            at + 1,
+            rest(prefix),
+            rest(actual_output)
+        ));
+    }
+    if let Some(suffix) = &case.out_suffix
+        && let Some(at) = mismatch(suffix.chars().rev(), actual_output.chars().rev())
+    {
+        let rest = |text: &str| {
+            let rest = text.chars().rev().skip(at).take(FIRST_DIFF_CONTEXT);
+            rest.collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .collect::<String>()
+        };
+        problems.push(format!(
+            "{STYLE_DELETED}suffix differs at char {} from the end:{STYLE_DELETED:#} expected {:?}, got {:?}\n",
+            at + 1,
+            rest(suffix),
+            rest(actual_output)
+        ));
+    }
+    let status = Outcome::new(problems.is_empty(), case.should_fail);
+    let output = actual_output.strip_suffix('\n').unwrap_or(actual_output);
+    let mut report = format!("{}\n{output}\n", case_header(status, note));
+    report.extend(problems);
+    report.push('\n');
+    (status, report)
+}
+
+/// The index of the first char of `expected` that `actual` does not match,
+/// if any, where running out of `actual` counts as not matching.
+fn mismatch(
+    expected: impl Iterator<Item = char>,
+    mut actual: impl Iterator<Item = char>,
+) -> Option<usize> {
+    expected
+        .enumerate()
+        .find(|&(_, c)| actual.next() != Some(c))
+        .map(|(index, _)| index)
+}
+
+/// The note followed by the runtime and, given the average runtime of
+/// earlier runs, its difference from it, like `note (42ms, +8ms vs avg)`.
+fn timed_note(note: &str, duration: Duration, average: Option<Duration>) -> String {
+    let millis = |duration: Duration| i128::try_from(duration.as_millis()).unwrap_or(i128::MAX);
+    match average {
+        Some(average) => format!(
+            "{note} ({}ms, {:+}ms vs avg)",
+            millis(duration),
+            millis(duration) - millis(average)
+        ),
+        None => format!("{note} ({}ms)", millis(duration)),
+    }
+}
+
+/// Asks a question on the terminal, even when stdin is redirected, and
+/// returns the trimmed, lowercased answer.
+fn prompt(question: &str) -> Result<String> {
+    let tty = if cfg!(windows) { "CONIN$" } else { "/dev/tty" };
+    let mut terminal = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(tty)
+        .context("Failed to open the terminal for confirmation")?;
+    terminal.write_all(question.as_bytes())?;
+    let mut answer = String::new();
+    std::io::BufRead::read_line(&mut std::io::BufReader::new(terminal), &mut answer)?;
+    Ok(answer.trim().to_lowercase())
+}
+
+/// The outcome and report of a case that failed before its output could be
+/// compared.
+fn failed_case(case: &TestCase, duration: Duration, reason: &str) -> (CaseOutcome, String) {
+    let status = if case.should_fail {
+        Outcome::ExpectedFailure
+    } else {
+        Outcome::Failed
+    };
+    let report = format!("{}\n{reason}\n", case_header(status, case.note()));
+    let outcome = CaseOutcome {
+        status,
+        duration,
+        actual_output: None,
+    };
+    (outcome, report)
+}
+
+/// The `--dots` char of an outcome, styled like the case's header.
+fn dot(status: Outcome) -> String {
+    let dot = match status {
+        Outcome::Passed | Outcome::ExpectedFailure => '.',
+        Outcome::Failed | Outcome::UnexpectedPass => 'F',
+        Outcome::NotRun => 'T',
+        Outcome::Skipped => 'S',
+    };
+    let style = status.style();
+    format!("{style}{dot}{style:#}")
+}
+
+/// The outcome of a case that the suite timeout cut short or left out.
+fn not_run_case(case: &TestCase) -> (CaseOutcome, String) {
+    let report = format!("{}\n", case_header(Outcome::NotRun, case.note()));
+    let outcome = CaseOutcome {
+        status: Outcome::NotRun,
+        duration: Duration::ZERO,
+        actual_output: None,
+    };
+    (outcome, report)
+}
+
+/// The case note, styled and suffixed according to `outcome`.
+fn case_header(outcome: Outcome, note: &str) -> String {
+    let style = outcome.style();
+    format!("{style}{note}{}{style:#}", outcome.suffix())
+}
+
+fn substitute_placeholders(
+    text: &str,
+    vars: &BTreeMap<String, String>,
+    allow_unresolved: bool,
+) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + len + 4];
+        let name = placeholder[2..placeholder.len() - 2].trim();
+        out.push_str(&rest[..start]);
+        match vars.get(name).cloned().or_else(|| std::env::var(name).ok()) {
+            Some(value) => out.push_str(&value),
+            None if allow_unresolved => out.push_str(placeholder),
+            None => anyhow::bail!("Unresolved placeholder: {placeholder}"),
+        }
+        rest = &rest[start + len + 4..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Expands `$NAME` and `${NAME}` from the process environment, with `$$`
+/// standing for a literal `$`. Unset variables expand to nothing, or are an
+/// error if `strict`.
+fn expand_env(text: &str, strict: bool) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix('$') {
+            out.push('$');
+            rest = escaped;
+            continue;
+        }
+        let (name, len) =
+            if let Some(end) = after.strip_prefix('{').and_then(|braced| braced.find('}')) {
+                (&after[1..=end], end + 2)
+            } else {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            };
+        if name.is_empty() {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        match std::env::var(name) {
+            Ok(value) => out.push_str(&value),
+            Err(_) if strict => anyhow::bail!("Unset environment variable: {name}"),
+            Err(_) => {}
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_program(
+    program: impl AsRef<OsStr>,
+    args: &str,
+    input: &[&str],
+    env: &BTreeMap<String, String>,
+    dir: Option<&Path>,
+    deadline: Option<Instant>,
+    merge_stderr: bool,
+    retries: u32,
+) -> Result<Output, DiffError> {
+    let program = program.as_ref();
+    tracing::debug!(?program, args, merge_stderr, "spawning program");
+    let mut command = Command::new(program);
+    command
+        .stdin(Stdio::piped())
+        .args(args.split_whitespace())
+        .envs(env);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let merged = if merge_stderr {
+        // Both streams write to one pipe, so their output stays in order
+        let (reader, writer) =
+            std::io::pipe().map_err(DiffError::io("Failed to create output pipe"))?;
+        let stderr = writer
+            .try_clone()
+            .map_err(DiffError::io("Failed to create output pipe"))?;
+        command.stdout(writer).stderr(stderr);
+        Some(reader)
+    } else {
+        command.stdout(Stdio::piped());
+        None
+    };
+    let spawned = spawn_with_retries(&mut command, retries, Command::spawn);
+    // Close the write ends held by `command`, or reading never ends
+    drop(command);
+    let mut command = spawned.map_err(|source| DiffError::SpawnFailed {
+        program: program.into(),
+        source,
+    })?;
+    // Output is only watched while writing when there are replies to wait for
+    let stdout: Option<Box<dyn Read + Send>> = match merged {
+        Some(reader) => Some(Box::new(reader)),
+        None if input.len() > 1 => command
+            .stdout
+            .take()
+            .map(|stdout| Box::new(stdout) as Box<dyn Read + Send>),
+        None => None,
+    };
+    let (arrived, replied) = mpsc::channel();
+    // Read while the program runs so that it never blocks on a full pipe
+    let stdout = stdout.map(|mut reader| {
+        std::thread::spawn(move || {
+            let mut bytes = Vec::new();
+            let mut chunk = [0; 4096];
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => break Ok(bytes),
+                    Ok(read) => {
+                        bytes.extend_from_slice(&chunk[..read]);
+                        // The writer stops listening once every input is written
+                        let _ = arrived.send(());
+                    }
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(err) => break Err(err),
+                }
+            }
+        })
+    });
+
+    for (index, input) in input.iter().enumerate() {
+        if index > 0 {
+            await_reply(&replied);
+        }
+        // Output from before this input does not answer it
+        while replied.try_recv().is_ok() {}
+        tracing::trace!(bytes = input.len(), "writing stdin");
+        let written = command
+            .stdin
+            .as_mut()
+            .ok_or_else(|| std::io::Error::other("stdin is not piped"))
+            .and_then(|stdin| stdin.write_all(input.as_bytes()));
+        match written {
+            // The program exited or closed stdin without reading all of it
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {
+                tracing::debug!("program closed stdin before reading all input");
+                break;
+            }
+            written => written.map_err(DiffError::io("Failed to write input to program"))?,
+        }
+    }
+
+    tracing::trace!("waiting for program");
+    let mut output = match deadline {
+        Some(deadline) => wait_until(command, deadline).map_err(|err| match err {
+            Some(source) => DiffError::io("Failed to get program output")(source),
+            None => DiffError::Timeout {
+                program: program.into(),
+            },
+        })?,
+        None => command
+            .wait_with_output()
+            .map_err(DiffError::io("Failed to get program output"))?,
+    };
+    if let Some(stdout) = stdout {
+        output.stdout = stdout
+            .join()
+            .expect("the output reader does not panic")
+            .map_err(DiffError::io("Failed to get program output"))?;
+    }
+    tracing::debug!(status = %output.status, stdout_bytes = output.stdout.len(), "program exited");
+    Ok(output)
+}
+
+/// How long a program gets to start replying to one of several inputs
+/// before the next is written anyway.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a program's output must pause for its reply to count as done.
+const REPLY_PAUSE: Duration = Duration::from_millis(50);
+
+/// Waits for the program to reply to the last input written: for output to
+/// arrive and then to pause.
+fn await_reply(replied: &Receiver<()>) {
+    if replied.recv_timeout(REPLY_TIMEOUT).is_ok() {
+        while replied.recv_timeout(REPLY_PAUSE).is_ok() {}
+    }
+}
+
+const DEFAULT_SPAWN_RETRIES: u32 = 3;
+
+/// Delay before the first retry of a failed spawn, doubled for each further one.
+const SPAWN_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Quotes `word` for a POSIX shell, leaving words without special chars
+/// as they are.
+fn shell_quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Spawns the command with `spawn`, normally `Command::spawn`, retrying up to
+/// `retries` times after errors that may go away once other processes exit,
+/// such as hitting the process limit.
+fn spawn_with_retries(
+    command: &mut Command,
+    retries: u32,
+    mut spawn: impl FnMut(&mut Command) -> std::io::Result<Child>,
+) -> std::io::Result<Child> {
+    let mut backoff = SPAWN_BACKOFF;
+    for _ in 0..retries {
+        match spawn(command) {
+            Err(err) if is_transient(&err) => {
+                tracing::debug!(%err, ?backoff, "retrying spawn");
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+            spawned => return spawned,
+        }
+    }
+    spawn(command)
+}
+
+/// Whether a spawn error is due to a temporary lack of resources rather than
+/// e.g. a missing program.
+fn is_transient(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        err.kind(),
+        ErrorKind::WouldBlock
+            | ErrorKind::ResourceBusy
+            | ErrorKind::OutOfMemory
+            | ErrorKind::Interrupted
+    )
+}
+
+/// Like `Child::wait_with_output`, but kills the child at `deadline`, which
+/// is reported as a `None` error.
+fn wait_until(mut child: Child, deadline: Instant) -> Result<Output, Option<std::io::Error>> {
+    drop(child.stdin.take());
+    let mut stdout = child.stdout.take();
+    let reader = std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        stdout
+            .as_mut()
+            .map_or(Ok(0), |stdout| stdout.read_to_end(&mut bytes))
+            .map(|_| bytes)
+    });
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(None);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = reader.join().expect("the stdout reader does not panic")?;
+    Ok(Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
+
+fn read_env_file(path: &Path) -> Result<BTreeMap<String, String>, DiffError> {
+    dotenvy::from_path_iter(path)
+        .and_then(Iterator::collect)
+        .map_err(DiffError::parse(format!(
+            "Failed to read env file: {}",
+            path.display()
+        )))
+}
+
+/// How input files are turned into text.
+#[derive(Clone, Copy)]
+struct ReadOptions {
+    /// Transparently decompress gzip files
+    decompress: bool,
+    /// Largest accepted file size in bytes, after decompression
+    max_size: Option<u64>,
+    /// How long fetching a URL input may take
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    timeout: Option<Duration>,
+    /// Encoding of the files, which are decoded to UTF-8
+    encoding: &'static Encoding,
+}
+
+/// Looks up an encoding by one of its WHATWG labels.
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("unknown encoding: {label}"))
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const DEFAULT_MAX_INPUT_SIZE: u64 = 100 * 1024 * 1024;
+
+fn read_file_with_encoding(
+    path: impl AsRef<Path>,
+    options: ReadOptions,
+) -> Result<String, DiffError> {
+    let path = path.as_ref();
+    let check_size = |size: u64| match options.max_size {
+        Some(limit) if size > limit => Err(DiffError::InputTooLarge {
+            path: path.to_owned(),
+            size,
+            limit,
+        }),
+        _ => Ok(()),
+    };
+    let metadata = std::fs::metadata(path).map_err(DiffError::io(format!(
+        "Failed to read file: {}",
+        path.display()
+    )))?;
+    check_size(metadata.len())?;
+    let mut bytes = std::fs::read(path).map_err(DiffError::io(format!(
+        "Failed to read file: {}",
+        path.display()
+    )))?;
+    let gzipped = path.extension().is_some_and(|extension| extension == "gz")
+        || bytes.starts_with(&GZIP_MAGIC);
+    if options.decompress && gzipped {
+        let mut decompressed = Vec::new();
+        // Stop one byte past the limit, which is enough to reject the file
+        let limit = options
+            .max_size
+            .map_or(u64::MAX, |limit| limit.saturating_add(1));
+        flate2::read::MultiGzDecoder::new(bytes.as_slice())
+            .take(limit)
+            .read_to_end(&mut decompressed)
+            .map_err(DiffError::io(format!(
+                "Failed to decompress file: {}",
+                path.display()
+            )))?;
+        check_size(decompressed.len() as u64)?;
+        bytes = decompressed;
+    }
+    if options.encoding == encoding_rs::UTF_8 {
+        return String::from_utf8(bytes).map_err(DiffError::parse(format!(
+            "File is not valid UTF-8: {}",
+            path.display()
+        )));
+    }
+    options
+        .encoding
+        .decode_without_bom_handling_and_without_replacement(&bytes)
+        .map(Cow::into_owned)
+        .ok_or_else(|| DiffError::ParseError {
+            context: format!(
+                "File is not valid {}: {}",
+                options.encoding.name(),
+                path.display()
+            ),
+            source: "malformed byte sequence".into(),
+        })
+}
+
+/// Whether a file mode input names a URL rather than a path.
+fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// Reads a file mode input, fetching it if it is a URL.
+fn read_input(input: &str, options: ReadOptions) -> Result<String, DiffError> {
+    if is_url(input) {
+        fetch_url(input, options)
+    } else {
+        read_file_with_encoding(input, options)
+    }
+}
+
+#[cfg(feature = "http")]
+fn fetch_url(url: &str, options: ReadOptions) -> Result<String, DiffError> {
+    let fetch_failed = |source: ureq::Error| DiffError::FetchFailed {
+        url: url.to_owned(),
+        source: Box::new(source),
+    };
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(options.timeout)
+        .build()
+        .into();
+    // Statuses other than 2xx are returned as errors
+    let mut response = agent.get(url).call().map_err(fetch_failed)?;
+    response
+        .body_mut()
+        .with_config()
+        .limit(options.max_size.unwrap_or(u64::MAX))
+        .read_to_string()
+        .map_err(fetch_failed)
+}
+
+#[cfg(not(feature = "http"))]
+fn fetch_url(url: &str, _options: ReadOptions) -> Result<String, DiffError> {
+    Err(DiffError::FetchFailed {
+        url: url.to_owned(),
+        source: "URL inputs need a build with the `http` feature".into(),
+    })
+}
+
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+enum Granularity {
+    /// Characters for small inputs and lines for larger ones
+    #[default]
+    Auto,
+    /// Highlight individual changed characters
+    Char,
+    /// Highlight whole changed lines
+    Line,
+}
+
+/// The diff engine mode of `diff-match-patch-rs` used for char diffs.
+#[derive(ValueEnum, Clone, Copy, Default)]
+enum Engine {
+    /// Diffs chars, which is slower but never splits a multi-byte char
+    #[default]
+    Compat,
+    /// Diffs bytes, which is faster on large inputs; falls back to `compat`
+    /// when a change would split a multi-byte char
+    Efficient,
+}
+
+impl Engine {
+    /// Diffs the inputs, giving up on a minimal diff after `timeout` unless
+    /// it is zero.
+    fn diff(self, left: &str, right: &str, timeout: Duration) -> Result<DiffVec, DiffError> {
+        tracing::trace!(
+            left_len = left.len(),
+            right_len = right.len(),
+            "computing diff"
+        );
+        let mut dmp = DiffMatchPatch::new();
+        let millis = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        dmp.set_timeout((millis > 0).then_some(millis));
+        match self {
+            Self::Compat => dmp
+                .diff_main::<Compat>(left, right)
+                .map(DiffVec)
+                .map_err(DiffError::DiffEngineError),
+            Self::Efficient => {
+                let diffs = dmp
+                    .diff_main::<Efficient>(left, right)
+                    .map_err(DiffError::DiffEngineError)?;
+                let chars = diffs
+                    .iter()
+                    .map(|diff| {
+                        let text = std::str::from_utf8(diff.data()).ok()?;
+                        Some(Diff::new(diff.op(), &text.chars().collect::<Vec<_>>()))
+                    })
+                    .collect::<Option<Vec<_>>>();
+                match chars {
+                    Some(chars) => Ok(DiffVec(chars)),
+                    None => Self::Compat.diff(left, right, timeout),
+                }
+            }
+        }
+    }
+}
+
+/// The algorithm used for line diffs.
+#[derive(ValueEnum, Clone, Copy, Default)]
+enum Algorithm {
+    /// `diff-match-patch`, which may trade a minimal diff for speed
+    #[default]
+    Dmp,
+    /// Myers' algorithm, which finds the shortest diff like classic `diff`
+    Myers,
+}
+
+/// How two texts are diffed.
+#[derive(Clone, Copy)]
+struct DiffOptions {
+    granularity: Granularity,
+    /// Input size in bytes from which `Granularity::Auto` diffs by line
+    granularity_threshold: usize,
+    /// Diff similar changed lines of a line diff by char
+    refine: bool,
+    /// Engine mode for char diffs
+    engine: Engine,
+    algorithm: Algorithm,
+    /// How long a diff may take before settling for a less minimal one
+    timeout: Duration,
+    /// Merge consecutive segments with the same op
+    collate: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            granularity: Granularity::Auto,
+            granularity_threshold: DEFAULT_GRANULARITY_THRESHOLD,
+            refine: false,
+            engine: Engine::Compat,
+            algorithm: Algorithm::Dmp,
+            timeout: DEFAULT_DIFF_TIMEOUT,
+            collate: true,
+        }
+    }
+}
+
+const DEFAULT_GRANULARITY_THRESHOLD: usize = 2048;
+
+/// The diff timeout of `diff-match-patch-rs` itself.
+const DEFAULT_DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Minimum `DiffVec::similarity` for a deleted and an inserted line to be
+/// treated as one changed line.
+const SIMILARITY_CUTOFF: f64 = 0.75;
+
+impl DiffOptions {
+    /// The granularity used for the given inputs, with `Auto` resolved by
+    /// the size of the larger one.
+    fn granularity_for(&self, left: &str, right: &str) -> Granularity {
+        match self.granularity {
+            Granularity::Auto if left.len().max(right.len()) < self.granularity_threshold => {
+                Granularity::Char
+            }
+            Granularity::Auto => Granularity::Line,
+            granularity => granularity,
+        }
+    }
+
+    fn diff(&self, left: &str, right: &str) -> Result<DiffVec> {
+        let diff = match self.granularity_for(left, right) {
+            Granularity::Line if self.refine => self.line_diff(left, right)?.refine()?,
+            Granularity::Line => return self.line_diff(left, right),
+            _ => self.engine.diff(left, right, self.timeout)?,
+        };
+        Ok(self.collated(diff))
+    }
+
+    fn line_diff(&self, left: &str, right: &str) -> Result<DiffVec> {
+        let diff = match self.algorithm {
+            Algorithm::Dmp => compute_line_diff(left, right, self.timeout)?,
+            Algorithm::Myers => myers::line_diff(left, right),
+        };
+        Ok(self.collated(diff))
+    }
+
+    fn collated(&self, diff: DiffVec) -> DiffVec {
+        if self.collate {
+            DiffVec(collate(diff.0))
+        } else {
+            diff
+        }
+    }
+}
+
+fn compute_diff(left: &str, right: &str) -> Result<DiffVec, DiffError> {
+    Engine::Compat.diff(left, right, DEFAULT_DIFF_TIMEOUT)
+}
+
+/// Computes a diff in which every segment consists of whole lines.
+///
+/// Each distinct line is mapped to a single char, the resulting strings are
+/// diffed, and the segments are mapped back to the lines they stand for.
+fn compute_line_diff(left: &str, right: &str, timeout: Duration) -> Result<DiffVec> {
+    let mut lines = Vec::new();
+    let mut tokens = HashMap::new();
+    let mut encode = |text: &'_ str| -> Result<String> {
+        text.split_inclusive('\n')
+            .map(|line| {
+                let next = u32::try_from(lines.len())?;
+                let token = *tokens.entry(line.to_owned()).or_insert_with(|| {
+                    lines.push(line.to_owned());
+                    next
+                });
+                // Skip the surrogate range, which has no chars
+                let code = if token < 0xD800 { token } else { token + 0x800 };
+                char::from_u32(code).context("Too many distinct lines to diff")
+            })
+            .collect()
+    };
+    let (left, right) = (encode(left)?, encode(right)?);
+
+    let diffs = Engine::Compat
+        .diff(&left, &right, timeout)?
+        .0
+        .into_iter()
+        .map(|diff| {
+            let data = diff
+                .data()
+                .iter()
+                .flat_map(|&token| {
+                    let code = u32::from(token);
+                    let index = if code < 0xD800 { code } else { code - 0x800 };
+                    lines[index as usize].chars()
+                })
+                .collect::<Vec<_>>();
+            Diff::new(diff.op(), &data)
+        })
+        .collect();
+    Ok(DiffVec(diffs))
+}
+
+fn files_line_diff(
+    left: &str,
+    right: &str,
+    options: ReadOptions,
+    diff_options: DiffOptions,
+) -> Result<DiffVec> {
+    diff_options.line_diff(&read_input(left, options)?, &read_input(right, options)?)
+}
+
+struct DiffVec(Vec<Diff<char>>);
+
+/// The char offsets into the left and right inputs at which each segment
+/// starts, with its operation, which `first_difference` uses to find where
+/// the inputs diverge. The line renderers count lines instead.
+///
+/// Cleanup moves segment boundaries, so the offsets are computed from the
+/// final segments rather than tracked while diffing.
+fn index_diffs(diffs: &[Diff<char>]) -> Vec<(usize, usize, Ops)> {
+    let (mut left, mut right) = (0, 0);
+    diffs
+        .iter()
+        .map(|diff| {
+            let start = (left, right, diff.op());
+            let len = diff.data().len();
+            match diff.op() {
+                Ops::Equal => (left, right) = (left + len, right + len),
+                Ops::Delete => left += len,
+                Ops::Insert => right += len,
+            }
+            start
+        })
+        .collect()
+}
+
+/// Merges every run of consecutive segments with the same op into one.
+fn collate(diffs: Vec<Diff<char>>) -> Vec<Diff<char>> {
+    let mut collated: Vec<Diff<char>> = Vec::with_capacity(diffs.len());
+    for diff in diffs {
+        match collated.last_mut() {
+            Some(last) if last.op() == diff.op() => {
+                let data = [last.data(), diff.data()].concat();
+                *last = Diff::new(diff.op(), &data);
+            }
+            _ => collated.push(diff),
+        }
+    }
+    collated
+}
+
+/// Char counts describing the size of a diff relative to its inputs.
+#[derive(Clone, Copy, Default)]
+struct DiffSummary {
+    inserted: usize,
+    deleted: usize,
+    left_len: usize,
+    right_len: usize,
+}
+
+impl DiffSummary {
+    /// The summary of comparing `text` with itself.
+    fn unchanged(text: &str) -> Self {
+        let len = text.chars().count();
+        Self {
+            left_len: len,
+            right_len: len,
+            ..Self::default()
+        }
+    }
+
+    /// Describes the changed share of both inputs, e.g. `changed 18% (42/230 chars)`.
+    fn changed_share(&self) -> String {
+        let changed = self.inserted + self.deleted;
+        let total = self.left_len + self.right_len;
+        let percent = (changed * 100).checked_div(total).unwrap_or_default();
+        format!("changed {percent}% ({changed}/{total} chars)")
+    }
+}
+
+impl std::ops::AddAssign for DiffSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.inserted += other.inserted;
+        self.deleted += other.deleted;
+        self.left_len += other.left_len;
+        self.right_len += other.right_len;
+    }
+}
+
+impl DiffVec {
+    fn summary(&self) -> DiffSummary {
+        let mut summary = DiffSummary::default();
+        for diff in &self.0 {
+            let len = diff.size();
+            match diff.op() {
+                Ops::Delete => {
+                    summary.deleted += len;
+                    summary.left_len += len;
+                }
+                Ops::Equal => {
+                    summary.left_len += len;
+                    summary.right_len += len;
+                }
+                Ops::Insert => {
+                    summary.inserted += len;
+                    summary.right_len += len;
+                }
+            }
+        }
+        summary
+    }
+    /// Twice the unchanged chars over the total chars of both sides, from 0
+    /// for entirely different sides to 1 for equal ones.
+    #[allow(clippy::cast_precision_loss)]
+    fn similarity(&self) -> f64 {
+        let summary = self.summary();
+        let total = summary.left_len + summary.right_len;
+        let unchanged = total - summary.inserted - summary.deleted;
+        if total == 0 {
+            1.0
+        } else {
+            unchanged as f64 / total as f64
+        }
+    }
+
+    /// Replaces each pair of similar deleted and inserted lines in a line diff
+    /// with the char diff between them.
+    fn refine(self) -> Result<Self> {
+        let mut refined = Vec::with_capacity(self.0.len());
+        let mut segments = self.0.into_iter().peekable();
+        while let Some(segment) = segments.next() {
+            let insert = match segment.op() {
+                Ops::Delete => segments.next_if(|next| next.op() == Ops::Insert),
+                _ => None,
+            };
+            let Some(insert) = insert else {
+                refined.push(segment);
+                continue;
+            };
+            let deleted = segment.data().iter().collect::<String>();
+            let inserted = insert.data().iter().collect::<String>();
+            let mut deleted_lines = deleted.split_inclusive('\n');
+            let mut inserted_lines = inserted.split_inclusive('\n');
+            for (left, right) in deleted_lines.by_ref().zip(inserted_lines.by_ref()) {
+                let pair = compute_diff(left, right)?;
+                if pair.similarity() >= SIMILARITY_CUTOFF {
+                    refined.extend(pair.0);
+                } else {
+                    refined.push(Diff::delete(&left.chars().collect::<Vec<_>>()));
+                    refined.push(Diff::insert(&right.chars().collect::<Vec<_>>()));
+                }
+            }
+            let rest = |lines: std::str::SplitInclusive<'_, char>| {
+                lines.flat_map(str::chars).collect::<Vec<_>>()
+            };
+            let (deleted_rest, inserted_rest) = (rest(deleted_lines), rest(inserted_lines));
+            if !deleted_rest.is_empty() {
+                refined.push(Diff::delete(&deleted_rest));
+            }
+            if !inserted_rest.is_empty() {
+                refined.push(Diff::insert(&inserted_rest));
+            }
+        }
+        Ok(Self(refined))
+    }
+
+    /// The unchanged segments of the diff, without the changes between them.
+    fn equal_only(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .filter(|diff| diff.op() == Ops::Equal)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// The diff with every unchanged segment shorter than `min` chars between
+    /// two changes made part of them, so changes close together show as one.
+    fn absorbed(&self, min: usize) -> Self {
+        let mut absorbed = Vec::new();
+        let (mut deleted, mut inserted) = (Vec::new(), Vec::new());
+        let flush = |absorbed: &mut Vec<_>, deleted: &mut Vec<char>, inserted: &mut Vec<char>| {
+            if !deleted.is_empty() {
+                absorbed.push(Diff::delete(&std::mem::take(deleted)));
+            }
+            if !inserted.is_empty() {
+                absorbed.push(Diff::insert(&std::mem::take(inserted)));
+            }
+        };
+        for (index, diff) in self.0.iter().enumerate() {
+            let between_changes = index > 0 && index + 1 < self.0.len();
+            match diff.op() {
+                Ops::Equal if between_changes && diff.size() < min => {
+                    deleted.extend(diff.data());
+                    inserted.extend(diff.data());
+                }
+                Ops::Equal => {
+                    flush(&mut absorbed, &mut deleted, &mut inserted);
+                    absorbed.push(diff.clone());
+                }
+                Ops::Delete => deleted.extend(diff.data()),
+                Ops::Insert => inserted.extend(diff.data()),
+            }
+        }
+        flush(&mut absorbed, &mut deleted, &mut inserted);
+        Self(absorbed)
+    }
+
+    /// The diff with every unchanged segment longer than `limit` chars
+    /// replaced by a marker giving its length.
+    fn folded(&self, limit: usize) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|diff| match diff.op() {
+                    Ops::Equal if diff.size() > limit => {
+                        let marker = format!("…[{} chars]…", diff.size());
+                        Diff::equal(&marker.chars().collect::<Vec<_>>())
+                    }
+                    _ => diff.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    /// The length of the longest unchanged segment, counted in lines for a
+    /// line diff and in chars otherwise.
+    fn longest_equal_run(&self, lines: bool) -> usize {
+        self.0
+            .iter()
+            .filter(|diff| diff.op() == Ops::Equal)
+            .map(|diff| {
+                if lines {
+                    diff.data().split_inclusive(|&c| c == '\n').count()
+                } else {
+                    diff.size()
+                }
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// The Levenshtein distance between both sides.
+    fn distance(&self) -> usize {
+        DiffMatchPatch::new().diff_levenshtein(&self.0)
+    }
+    /// The text shared by both sides before the first change, if any.
+    fn common_prefix(&self) -> Option<String> {
+        let first_change = self.0.iter().position(|diff| diff.op() != Ops::Equal)?;
+        Some(self.0[..first_change].iter().flat_map(Diff::data).collect())
+    }
+    /// Counts the inserted and deleted lines, treating a partial line as a
+    /// whole one.
+    fn line_changes(&self) -> (usize, usize) {
+        let count = |op| {
+            self.0
+                .iter()
+                .filter(|diff| diff.op() == op)
+                .map(|diff| {
+                    let newlines = diff.data().iter().filter(|&&c| c == '\n').count();
+                    newlines + usize::from(diff.data().last().is_some_and(|&c| c != '\n'))
+                })
+                .sum()
+        };
+        (count(Ops::Insert), count(Ops::Delete))
+    }
+
+    /// The segments as a JSON array of `{"op", "text"}` objects.
+    fn to_json(&self) -> serde_json::Value {
+        self.0
+            .iter()
+            .map(|diff| {
+                let op = match diff.op() {
+                    Ops::Delete => "delete",
+                    Ops::Equal => "equal",
+                    Ops::Insert => "insert",
+                };
+                serde_json::json!({"op": op, "text": diff.data().iter().collect::<String>()})
+            })
+            .collect()
+    }
+
+    /// The segment styles of each char, marking spaces and tabs per
+    /// `whitespace` and highlighting trailing spaces in the marked segments.
+    fn styled_chars(&self, whitespace: Option<Whitespace>) -> Vec<(anstyle::Style, char)> {
+        let chars = self
+            .0
+            .iter()
+            .flat_map(|diff| {
+                let marker = change_marker(diff.op()).map(|marker| (diff.op(), marker));
+                marker
+                    .into_iter()
+                    .chain(diff.data().iter().map(|&c| (diff.op(), c)))
+            })
+            .collect::<Vec<_>>();
+
+        // Whether each space is followed only by spaces up to the end of its
+        // line on its side of the diff
+        let mut trailing = vec![false; chars.len()];
+        for side in [Ops::Delete, Ops::Insert] {
+            let mut at_line_end = true;
+            for (index, &(op, c)) in chars.iter().enumerate().rev() {
+                if op != Ops::Equal && op != side {
+                    continue;
+                }
+                at_line_end = c == '\n' || (c == ' ' && at_line_end);
+                trailing[index] |= c == ' ' && at_line_end;
+            }
+        }
+
+        chars
+            .into_iter()
+            .zip(trailing)
+            .map(|((op, c), trailing)| {
+                let style = match op {
+                    Ops::Delete => STYLE_DELETED.style(),
+                    Ops::Equal => anstyle::Style::new(),
+                    Ops::Insert => STYLE_INSERTED.style(),
+                };
+                let marked = match whitespace {
+                    Some(Whitespace::All) => true,
+                    Some(Whitespace::Changed) => op != Ops::Equal,
+                    None => false,
+                };
+                match c {
+                    ' ' if marked && trailing => (STYLE_DELETED.style(), '·'),
+                    ' ' if marked => (style, '·'),
+                    '\t' if marked => (style, '→'),
+                    _ => (style, c),
+                }
+            })
+            .collect()
+    }
+
+    /// Renders the diff in color like `Display`, optionally marking
+    /// whitespace and breaking lines longer than `wrap` chars, with the
+    /// current style restarted after each break.
+    fn render(&self, whitespace: Option<Whitespace>, wrap: Option<usize>) -> String {
+        let mut out = String::new();
+        let mut current = anstyle::Style::new();
+        let mut column = 0;
+        for (style, c) in self.styled_chars(whitespace) {
+            if c != '\n' && wrap == Some(column) {
+                out.extend([format!("{current:#}\n{current}")]);
+                column = 0;
+            }
+            if style != current {
+                out.extend([format!("{current:#}{style}")]);
+                current = style;
+            }
+            column = if c == '\n' { 0 } else { column + 1 };
+            out.push(c);
+        }
+        out.extend([format!("{current:#}")]);
+        out
+    }
+
+    /// Renders the diff without colors, marking deletions as `[-text-]` and
+    /// insertions as `{+text+}`.
+    fn plain(&self) -> String {
+        let mut out = String::new();
+        for diff in &self.0 {
+            let text = diff.data().iter().copied().collect::<String>();
+            match diff.op() {
+                Ops::Delete => out.extend(["[-", &text, "-]"]),
+                Ops::Equal => out.push_str(&text),
+                Ops::Insert => out.extend(["{+", &text, "+}"]),
+            }
+        }
+        out
+    }
+}
+
+/// Where whitespace is made visible in inline diffs.
+#[derive(ValueEnum, Clone, Copy)]
+enum Whitespace {
+    /// Only in deleted and inserted text
+    Changed,
+    /// Everywhere
+    All,
+}
+
+/// The width at which inline diffs are wrapped.
+#[derive(Clone, Copy)]
+enum Wrap {
+    /// The terminal width, or 80 columns if it can't be determined
+    Auto,
+    Columns(NonZeroUsize),
+}
+
+impl Wrap {
+    fn parse(wrap: &str) -> Result<Self, String> {
+        match wrap {
+            "auto" => Ok(Self::Auto),
+            _ => wrap.parse().map(Self::Columns).map_err(|_| {
+                format!("expected a positive number of columns or auto, got {wrap:?}")
+            }),
+        }
+    }
+
+    fn columns(self) -> usize {
+        match self {
+            Self::Auto => terminal_size::terminal_size().map_or(80, |(width, _)| width.0.into()),
+            Self::Columns(columns) => columns.get(),
+        }
+    }
+}
+
+/// The char put before inline changes when the theme does not rely on color
+/// alone to tell them apart.
+fn change_marker(op: Ops) -> Option<char> {
+    match op {
+        Ops::Delete if theme::current().markers() => Some('-'),
+        Ops::Insert if theme::current().markers() => Some('+'),
+        _ => None,
+    }
+}
+
+impl std::fmt::Display for DiffVec {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for diff in &self.0 {
+            let marker = change_marker(diff.op());
+            let text = marker.into_iter().chain(diff.data().iter().copied());
+            let text = text.collect::<String>();
+            match diff.op() {
+                Ops::Delete => write!(f, "{STYLE_DELETED}{text}{STYLE_DELETED:#}"),
+                Ops::Equal => write!(f, "{text}"),
+                Ops::Insert => write!(f, "{STYLE_INSERTED}{text}{STYLE_INSERTED:#}"),
+            }?;
+        }
+        Ok(())
+    }
+}
+
+fn open_in_editor(diff: &str) -> Result<()> {
+    let editor = std::env::var("EDITOR")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_owned());
+    edit(&editor, diff)
+}
+
+/// Runs the `editor` command line on a temporary file holding the diff,
+/// removing the file once the editor exits.
+fn edit(editor: &str, diff: &str) -> Result<()> {
+    let mut editor = editor.split_whitespace();
+    let program = editor.next().unwrap_or_default();
+
+    // A new file with a random name, so that no other user can plant one in
+    // its place or read it
+    let mut file = tempfile::Builder::new()
+        .prefix("diff-")
+        .suffix(".txt")
+        .tempfile()
+        .context("Failed to create temporary diff file")?;
+    file.write_all(diff.as_bytes())
+        .context("Failed to write temporary diff file")?;
+    // Close the file before the editor opens it, keeping only the path
+    let path = file.into_temp_path();
+
+    let status = Command::new(program).args(editor).arg(&path).status();
+    path.close()
+        .context("Failed to remove temporary diff file")?;
+
+    let status = status.context(format!("Failed to start editor: {program}"))?;
+    if !status.success() {
+        anyhow::bail!("Editor exited with {status}");
+    }
+    Ok(())
+}
+
+const CLIPBOARD_ERROR: &str = "Failed to copy diff to clipboard (is a display server available?)";
+
+/// First argument that makes the program serve the clipboard contents read
+/// from stdin instead of parsing its command line.
+const CLIPBOARD_OWNER_ARG: &str = "__serve-clipboard";
+
+/// Copies the diff to the clipboard.
+///
+/// On Linux the clipboard is emptied once the process that set it exits, so
+/// a copy of this program started in the background keeps serving the diff
+/// until something else is copied.
+fn copy_to_clipboard(diff: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().context(CLIPBOARD_ERROR)?;
+    if !cfg!(target_os = "linux") {
+        return clipboard.set_text(diff).context(CLIPBOARD_ERROR);
+    }
+    drop(clipboard);
+    let program = std::env::current_exe().context(CLIPBOARD_ERROR)?;
+    // Left running on purpose, as it outlives this process
+    let mut owner = Command::new(program)
+        .arg(CLIPBOARD_OWNER_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .current_dir("/")
+        .spawn()
+        .context(CLIPBOARD_ERROR)?;
+    owner
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(diff.as_bytes())
+        .context(CLIPBOARD_ERROR)
+}
+
+/// Sets the clipboard to the text on stdin and serves it until something
+/// else is copied, as the background process of `copy_to_clipboard`.
+#[cfg(target_os = "linux")]
+fn serve_clipboard() -> Result<()> {
+    let mut text = String::new();
+    std::io::stdin()
+        .read_to_string(&mut text)
+        .context("Failed to read stdin")?;
+    hold_clipboard(&text, None).context(CLIPBOARD_ERROR)
+}
+
+/// Sets the clipboard and serves its contents to other programs until
+/// something else is copied or, if given, `deadline` passes.
+#[cfg(target_os = "linux")]
+fn hold_clipboard(text: &str, deadline: Option<Instant>) -> Result<(), arboard::Error> {
+    use arboard::SetExtLinux;
+    let mut clipboard = arboard::Clipboard::new()?;
+    let set = clipboard.set();
+    let set = match deadline {
+        Some(deadline) => set.wait_until(deadline),
+        None => set.wait(),
+    };
+    set.text(text)
+}
+
+const EXAMPLE_STRING: &str = r"
+ - note: test
+   args: arguments
+   input: input
+   out: output
+ - note: test
+   args: arguments
+   input: input
+   out: output
+ - note: test
+   args: arguments
+   input: input
+   out: output
+";
+
+/// Reads both files a line at a time up to the first line that differs,
+/// describing it, or returns `None` if the files are identical. Line
+/// endings, including a missing final newline, are not compared.
+fn first_differing_line(left: &Path, right: &Path) -> Result<Option<String>> {
+    let open = |path: &Path| -> Result<_> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        Ok(std::io::BufRead::lines(std::io::BufReader::new(file)))
+    };
+    first_differing_line_of(open(left)?, open(right)?)
+}
+
+/// Like `first_differing_line`, but over lines from any source, of which
+/// it takes no more than it needs.
+fn first_differing_line_of(
+    mut left_lines: impl Iterator<Item = std::io::Result<String>>,
+    mut right_lines: impl Iterator<Item = std::io::Result<String>>,
+) -> Result<Option<String>> {
+    for number in 1.. {
+        let (left_line, right_line) = (
+            left_lines.next().transpose()?,
+            right_lines.next().transpose()?,
+        );
+        if left_line == right_line {
+            if left_line.is_none() {
+                return Ok(None);
+            }
+            continue;
+        }
+        let describe = |line: Option<String>| {
+            line.map_or("<end of file>".to_owned(), |line| format!("{line:?}"))
+        };
+        return Ok(Some(format!(
+            "First difference at line {number}\n{STYLE_DELETED}-{STYLE_DELETED:#} {}\n{STYLE_INSERTED}+{STYLE_INSERTED:#} {}",
+            describe(left_line),
+            describe(right_line)
+        )));
+    }
+    unreachable!("the loop only ends by returning")
+}
+
+/// Number of chars shown on either side of the first difference.
+const FIRST_DIFF_CONTEXT: usize = 20;
+
+/// Describes where two inputs first diverge, with a little surrounding text
+/// from each side.
+fn first_difference(left: &str, right: &str) -> Result<String> {
+    let diff = compute_diff(left, right)?;
+    let Some(prefix) = diff.common_prefix() else {
+        return Ok("Inputs are identical".to_owned());
+    };
+    let line = prefix.matches('\n').count() + 1;
+    let column = prefix
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+    let (offset, _, _) = index_diffs(&diff.0)
+        .into_iter()
+        .find(|&(_, _, op)| op != Ops::Equal)
+        .expect("a diff with a common prefix has a change");
+    let excerpt = |text: &str| {
+        text.chars()
+            .skip(offset.saturating_sub(FIRST_DIFF_CONTEXT))
+            .take(offset.min(FIRST_DIFF_CONTEXT) + FIRST_DIFF_CONTEXT)
+            .collect::<String>()
+    };
+    Ok(format!(
+        "First difference at byte {} (line {line}, column {column})\n{STYLE_DELETED}-{STYLE_DELETED:#} {:?}\n{STYLE_INSERTED}+{STYLE_INSERTED:#} {:?}",
+        prefix.len(),
+        excerpt(left),
+        excerpt(right)
+    ))
+}
+
+/// An inclusive range of 1-based line numbers, either end of which may be
+/// left open.
+#[derive(Clone, Copy)]
+struct LineRange {
+    start: Option<usize>,
+    end: Option<usize>,
+}
+
+impl LineRange {
+    fn parse(range: &str) -> Result<Self, String> {
+        let (start, end) = range
+            .split_once(':')
+            .ok_or_else(|| format!("expected START:END, got {range:?}"))?;
+        let bound = |bound: &str| {
+            (!bound.is_empty())
+                .then(|| bound.parse::<usize>())
+                .transpose()
+                .map_err(|err| format!("invalid line number {bound:?}: {err}"))
+        };
+        Ok(Self {
+            start: bound(start)?,
+            end: bound(end)?,
+        })
+    }
+
+    /// The lines of `text` within the range, which is clamped to the lines
+    /// that exist.
+    fn slice(self, text: &str) -> &str {
+        // Byte offsets at which each line starts, followed by the text length
+        let starts = std::iter::once(0)
+            .chain(text.split_inclusive('\n').scan(0, |offset, line| {
+                *offset += line.len();
+                Some(*offset)
+            }))
+            .collect::<Vec<_>>();
+        let offset = |line: usize| starts.get(line).copied().unwrap_or(text.len());
+        let from = offset(self.start.unwrap_or(1).saturating_sub(1));
+        let to = self.end.map_or(text.len(), offset).max(from);
+        &text[from..to]
+    }
+}
+
+/// Splits `input` into the text before and after the first line equal to
+/// `delimiter`.
+fn split_at_delimiter(input: &str, delimiter: &str) -> Result<(String, String)> {
+    let mut start = 0;
+    for line in input.split_inclusive('\n') {
+        let end = start + line.len();
+        if line.trim_end_matches(['\n', '\r']) == delimiter {
+            return Ok((input[..start].to_owned(), input[end..].to_owned()));
+        }
+        start = end;
+    }
+    anyhow::bail!("Delimiter line {delimiter:?} not found on stdin")
+}
+
+/// Prints the inserted and deleted counts, in lines for line-based formats
+/// and chars otherwise, returning whether there are any.
+fn print_counts(out: &mut impl Write, diff: &DiffVec, format: Format) -> std::io::Result<bool> {
+    let (inserted, deleted) = match format {
+        Format::Inline | Format::Json => {
+            let summary = diff.summary();
+            (summary.inserted, summary.deleted)
+        }
+        Format::Unified | Format::Ndiff | Format::Oneline | Format::Patch => diff.line_changes(),
+    };
+    match format {
+        Format::Json => writeln!(out, r#"{{"ins":{inserted},"del":{deleted}}}"#)?,
+        _ => writeln!(out, "ins={inserted} del={deleted}")?,
+    }
+    Ok(inserted + deleted > 0)
+}
+
+fn file_header(left: &str, right: &str, show_times: bool) -> Result<String> {
+    let line = |marker, path: &str, style: anstyle::Style| -> Result<String> {
+        let mut line = format!("{marker} {path}");
+        if show_times && !is_url(path) {
+            let modified = std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .context(format!("Failed to read modification time: {path}"))?;
+            line = format!("{line}\t{}", humantime::format_rfc3339_seconds(modified));
+        }
+        Ok(format!("{style}{line}{style:#}"))
+    };
+    Ok(format!(
+        "{}\n{}",
+        line("---", left, STYLE_DELETED.style())?,
+        line("+++", right, STYLE_INSERTED.style())?
+    ))
+}
+
+/// Diffs the left input against its snapshot in `dir`, first storing it
+/// there if there is no snapshot yet or `--update-snapshots` is given.
+fn snapshot_diff(
+    out: &mut impl Write,
+    args: DiffArgs,
+    dir: &Path,
+    normalization: &Normalization,
+    read_options: ReadOptions,
+    diff_options: DiffOptions,
+) -> Result<ExitCode> {
+    let name = args.name.as_deref().unwrap_or_default();
+    if name.is_empty() || name.contains(['/', '\\']) || name == ".." {
+        anyhow::bail!("Invalid snapshot name: {name:?}");
+    }
+    let input = args.left.as_deref().unwrap_or_default();
+    let current = match args.mode {
+        Mode::Interactive => input.to_owned(),
+        Mode::File => read_input(input, read_options)?,
+        Mode::Program => anyhow::bail!("--snapshot-dir is not supported in program mode"),
+    };
+    let path = dir.join(format!("{name}.snap"));
+    let snapshot = if args.update_snapshots {
+        None
+    } else {
+        match std::fs::read_to_string(&path) {
+            Ok(snapshot) => Some(snapshot),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(err).context(format!("Failed to read snapshot: {}", path.display()));
+            }
+        }
+    };
+    let Some(snapshot) = snapshot else {
+        std::fs::create_dir_all(dir).context("Failed to create snapshot directory")?;
+        std::fs::write(&path, &current)
+            .context(format!("Failed to write snapshot: {}", path.display()))?;
+        writeln!(out, "Stored snapshot {}", path.display())?;
+        return Ok(ExitCode::Success);
+    };
+    let args = DiffArgs {
+        left: Some(path.display().to_string()),
+        right: Some(input.to_owned()),
+        ..args
+    };
+    let (snapshot, current) = (
+        normalization.apply(&snapshot),
+        normalization.apply(&current),
+    );
+    print_diff(out, &args, &snapshot, &current, diff_options)
+}
+
+/// Diffs the left input as of the git revision `rev` against its current
+/// contents.
+fn revision_diff(
+    out: &mut impl Write,
+    args: DiffArgs,
+    rev: &str,
+    normalization: &Normalization,
+    read_options: ReadOptions,
+    diff_options: DiffOptions,
+) -> Result<ExitCode> {
+    if !matches!(args.mode, Mode::File) {
+        anyhow::bail!("--git is only supported in file mode");
+    }
+    let path = args.left.clone().unwrap_or_default();
+    let committed = git_show(rev, Path::new(&path))?;
+    let current = read_input(&path, read_options)?;
+    let args = DiffArgs {
+        right: Some(path),
+        ..args
+    };
+    let (committed, current) = (
+        normalization.apply(&committed),
+        normalization.apply(&current),
+    );
+    print_diff(out, &args, &committed, &current, diff_options)
+}
+
+/// The contents of the file at `path` as committed at the git revision
+/// `rev`, from the repository the file is in.
+fn git_show(rev: &str, path: &Path) -> Result<String> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("Not a file: {}", path.display()))?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut object = OsString::from(format!("{rev}:./"));
+    object.push(name);
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("show")
+        .arg(object)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|source| DiffError::SpawnFailed {
+            program: "git".into(),
+            source,
+        })?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to read {} at {rev}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout)
+        .map_err(DiffError::parse(format!(
+            "{} at {rev} is not valid UTF-8",
+            path.display()
+        )))
+        .map_err(Into::into)
+}
+
+/// The names of the fixtures in `dir` with files that differ from the git
+/// revision `rev`, or `None` if git cannot tell, e.g. outside a repository.
+fn changed_fixtures(dir: &Path, rev: &str) -> Option<BTreeSet<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["diff", "--name-only", "--relative", rev, "--", "."])
+        .stdin(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Some(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|path| path.split_once('/').map(|(fixture, _)| fixture.to_owned()))
+                .collect(),
+        ),
+        Ok(output) => {
+            eprintln!(
+                "Running all fixtures, as git could not list the changes since {rev}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            None
+        }
+        Err(err) => {
+            eprintln!("Running all fixtures, as git could not be run: {err}");
+            None
+        }
+    }
+}
+
+/// Runs the test file given as the right input against the program given as
+/// the left one.
+fn run_program(
+    args: DiffArgs,
+    normalization: Normalization,
+    diff_options: DiffOptions,
+) -> Result<ExitCode> {
+    let json_pretty = args.json_pretty();
+    let options = RunOptions {
+        bench_runs: args.bench.then_some(args.bench_runs),
+        timing_histogram: args.timing_histogram,
+        group_summary: args.group_summary,
+        progress: args.progress && std::io::stderr().is_terminal(),
+        dots: args.dots && std::io::stderr().is_terminal(),
+        allow_unresolved: args.allow_unresolved,
+        expand_env: args.expand_env.then_some(args.strict_env),
+        strict_utf8: args.strict_utf8,
+        merge_stderr: args.merge_stderr,
+        require_success: args.require_success,
+        env_file: args.env_file,
+        normalization,
+        filter: args.filter,
+        diff: diff_options,
+        jobs: args.jobs.map_or(1, NonZeroUsize::get),
+        spawn_retries: args.spawn_retries,
+        summary_only: args.summary_only,
+        porcelain: args.porcelain,
+        events: args.events,
+        sort: args.sort,
+        suite_timeout: args.suite_timeout,
+        json_pretty,
+        report: args.report,
+        group: args.group,
+        since: args.since,
+        rerun: args
+            .rerun_failures
+            .as_deref()
+            .map(Report::load)
+            .transpose()?
+            .map(|report| report.failures()),
+        baseline: args
+            .baseline
+            .as_deref()
+            .map(Report::load)
+            .transpose()?
+            .map(|report| report.outcomes()),
+        cache: args.cache,
+        history: args.history,
+        on_failure: args.on_failure,
+        equal_via: args.equal_via,
+        update: match (args.update || args.accept_regressions, args.interactive) {
+            (_, true) => Some(Update::Confirm),
+            (true, false) => Some(Update::All),
+            (false, false) => None,
+        },
+        accept_regressions: args.accept_regressions,
+    };
+    let (mut program, test_file) = (
+        args.left.unwrap_or_default(),
+        args.right.unwrap_or_default(),
+    );
+    if args.expand_env {
+        program = expand_env(&program, args.strict_env)?;
+    }
+    if options.since.is_some() && !Path::new(&test_file).is_dir() {
+        anyhow::bail!("--since only applies to a fixtures directory");
+    }
+    if options.update.is_some() && Path::new(&test_file).is_dir() {
+        anyhow::bail!("--update only applies to a test file, not a fixtures directory");
+    }
+    TestRunner::new(&program, &test_file, options)?.run()
+}
+
+fn run_diff(out: &mut impl Write, args: DiffArgs) -> Result<ExitCode> {
+    let left_arg = args.left.as_deref().unwrap_or_default();
+    let right_arg = args.right.as_deref().unwrap_or_default();
+    let diff_options = args.diff_options();
+    let normalization = args.normalization();
+    let read_options = ReadOptions {
+        decompress: !args.no_decompress,
+        max_size: (!args.force_large).then_some(args.max_input_size),
+        timeout: args.timeout,
+        encoding: args.encoding.unwrap_or(encoding_rs::UTF_8),
+    };
+    if let Some(dir) = args.snapshot_dir.clone() {
+        return snapshot_diff(out, args, &dir, &normalization, read_options, diff_options);
+    }
+    if let Some(rev) = args.git.clone() {
+        return revision_diff(out, args, &rev, &normalization, read_options, diff_options);
+    }
+    let (left, right) = match args.mode {
+        Mode::Program => return run_program(args, normalization, diff_options),
+        Mode::Interactive if args.stdin => {
+            let mut input = String::new();
+            std::io::stdin()
+                .read_to_string(&mut input)
+                .context("Failed to read stdin")?;
+            split_at_delimiter(&input, &args.delimiter)?
+        }
+        _ if args.stdin => anyhow::bail!("--stdin is only supported in interactive mode"),
+        Mode::Interactive => (left_arg.to_owned(), right_arg.to_owned()),
+        Mode::File if Path::new(left_arg).is_dir() && Path::new(right_arg).is_dir() => {
+            let options = args.dir_options(read_options, normalization, diff_options)?;
+            let changed = dir::dirs_diff(out, Path::new(left_arg), Path::new(right_arg), &options)?;
+            return Ok(ExitCode::differs(changed));
+        }
+        Mode::File if args.stop_at_first_diff => {
+            let difference = first_differing_line(Path::new(left_arg), Path::new(right_arg))?;
+            writeln!(
+                out,
+                "{}",
+                difference.as_deref().unwrap_or("Files are identical")
+            )?;
+            return Ok(ExitCode::differs(difference.is_some()));
+        }
+        Mode::File if args.stat => {
+            let stat = dir::FileStat::new(
+                right_arg.into(),
+                &files_line_diff(left_arg, right_arg, read_options, diff_options)?,
+            );
+            let (summary, changed) = (stat.summary, stat.changed());
+            dir::print_stat(out, &[stat], summary)?;
+            return Ok(ExitCode::differs(changed));
+        }
+        Mode::File => (
+            read_input(left_arg, read_options)?,
+            read_input(right_arg, read_options)?,
+        ),
+    };
+
+    let (left, right) = if matches!(args.mode, Mode::Interactive) {
+        (
+            decode_input(&args, &left, "Left")?,
+            decode_input(&args, &right, "Right")?,
+        )
+    } else {
+        (left, right)
+    };
+    if args.show_inputs {
+        print_inputs(out, &left, &right)?;
+    }
+    let (left, right) = if args.null_data {
+        (swap_separators(&left), swap_separators(&right))
+    } else {
+        (left, right)
+    };
+    let slice = |text: String, range: Option<LineRange>| match range {
+        Some(range) => range.slice(&text).to_owned(),
+        None => text,
+    };
+    let (left, right) = (slice(left, args.left_range), slice(right, args.right_range));
+    let (left, right) = (normalization.apply(&left), normalization.apply(&right));
+    if args.first_diff {
+        writeln!(out, "{}", first_difference(&left, &right)?)?;
+        return Ok(ExitCode::differs(left != right));
+    }
+    if args.columns {
+        let changes = records::compare(&left, &right, &args.field_sep, &args.pair_sep);
+        for change in &changes {
+            writeln!(out, "{change}")?;
+        }
+        return Ok(ExitCode::differs(!changes.is_empty()));
+    }
+    print_diff(out, &args, &left, &right, diff_options)
+}
+
+fn print_inputs(out: &mut impl Write, left: &str, right: &str) -> std::io::Result<()> {
+    for (label, style, text) in [
+        ("Left", STYLE_DELETED, left),
+        ("Right", STYLE_INSERTED, right),
+    ] {
+        writeln!(out, "{style}{label} input:{style:#}\n{text}")?;
+    }
+    Ok(())
+}
+
+/// Applies `--decode` and `--binary` to an interactive mode input.
+fn decode_input(args: &DiffArgs, text: &str, side: &str) -> Result<String, DiffError> {
+    if args.decode == Decoding::None && !args.binary {
+        return Ok(text.to_owned());
+    }
+    let bytes = args.decode.decode(text, side)?;
+    if args.binary {
+        return Ok(decode::hex_dump(&bytes));
+    }
+    String::from_utf8(bytes).map_err(DiffError::parse(format!(
+        "Decoded {} input is not valid UTF-8 (use --binary to compare its bytes)",
+        side.to_lowercase()
+    )))
+}
+
+/// Exchanges NUL and line feed, turning NUL-separated records into lines
+/// and back.
+fn swap_separator(c: char) -> char {
+    match c {
+        '\0' => '\n',
+        '\n' => '\0',
+        c => c,
+    }
+}
+
+fn swap_separators(text: &str) -> String {
+    text.chars().map(swap_separator).collect()
+}
+
+/// Diffs the inputs and prints, copies or opens the rendered diff as
+/// requested by `args`.
+fn print_diff(
+    out: &mut impl Write,
+    args: &DiffArgs,
+    left: &str,
+    right: &str,
+    diff_options: DiffOptions,
+) -> Result<ExitCode> {
+    let full = match args.format {
+        Format::Inline | Format::Json => diff_options.diff(left, right)?,
+        Format::Unified | Format::Ndiff | Format::Oneline | Format::Patch => {
+            diff_options.line_diff(left, right)?
+        }
+    };
+    // Counted on the whole diff, but only the first segments are rendered
+    let truncated = args
+        .max_segments
+        .filter(|&max| full.0.len() > max)
+        .map(|max| DiffVec(full.0[..max].to_vec()));
+    let diff = truncated.as_ref().unwrap_or(&full);
+    let (left_path, right_path) = (
+        args.left.as_deref().unwrap_or_default(),
+        args.right.as_deref().unwrap_or_default(),
+    );
+    let equal = args.equal_only.then(|| diff.equal_only());
+    let inline = equal.as_ref().unwrap_or(diff);
+    let absorbed = args.min_equal_run.map(|min| inline.absorbed(min));
+    let inline = absorbed.as_ref().unwrap_or(inline);
+    let json_pretty = args.json_pretty();
+    let folded = args.fold.map(|limit| inline.folded(limit));
+    let inline = folded.as_ref().unwrap_or(inline);
+    let render = |color| -> Result<String> {
+        let rendered = match args.format {
+            Format::Inline if color && (args.wrap.is_some() || args.show_whitespace.is_some()) => {
+                inline.render(args.show_whitespace, args.wrap.map(Wrap::columns))
+            }
+            Format::Inline if color => inline.to_string(),
+            Format::Inline => inline.plain(),
+            Format::Unified => unified::render(diff, color, args.show_section.as_ref()),
+            Format::Ndiff => ndiff::render(diff, color)?,
+            Format::Oneline => {
+                let width = args.wrap.unwrap_or(Wrap::Auto).columns();
+                oneline::render(diff, color, width)
+            }
+            Format::Patch => match args.mode {
+                Mode::File => unified::patch(diff, left_path, right_path),
+                _ => unified::patch(diff, "left", "right"),
+            },
+            // JSON escapes both separators, so only the text needs swapping back
+            Format::Json if args.null_data => {
+                let swapped = diff.0.iter().map(|diff| {
+                    let data = diff.data().iter().map(|&c| swap_separator(c));
+                    Diff::new(diff.op(), &data.collect::<Vec<_>>())
+                });
+                to_json(&DiffVec(swapped.collect()).to_json(), json_pretty)?
+            }
+            Format::Json => to_json(&diff.to_json(), json_pretty)?,
+        };
+        Ok(if args.null_data {
+            swap_separators(&rendered)
+        } else {
+            rendered
+        })
+    };
+
+    if args.lcs {
+        let lines = matches!(
+            args.format,
+            Format::Unified | Format::Ndiff | Format::Oneline | Format::Patch
+        );
+        let lcs = full.longest_equal_run(lines);
+        match args.format {
+            Format::Json => writeln!(out, r#"{{"lcs":{lcs}}}"#)?,
+            _ => writeln!(out, "lcs={lcs}")?,
+        }
+        return Ok(ExitCode::differs(left != right));
+    }
+    if args.count_only {
+        return Ok(ExitCode::differs(print_counts(out, &full, args.format)?));
+    }
+    if let Some(max) = args.max_segments
+        && truncated.is_some()
+    {
+        eprintln!(
+            "Inputs differ extensively ({} segments); showing the first {max}",
+            full.0.len()
+        );
+    }
+
+    if args.clipboard {
+        copy_to_clipboard(&render(false)?)?;
+    }
+    if args.edit {
+        open_in_editor(&render(false)?)?;
+    } else {
+        // Patches carry headers of their own
+        let patch = matches!(args.format, Format::Patch);
+        if matches!(args.mode, Mode::File) && !args.no_header && !patch {
+            writeln!(
+                out,
+                "{}",
+                file_header(left_path, right_path, args.show_times)?
+            )?;
+        }
+        match args.format {
+            Format::Inline | Format::Json => writeln!(out, "{}", render(true)?)?,
+            Format::Unified | Format::Ndiff | Format::Oneline | Format::Patch => {
+                write!(out, "{}", render(true)?)?;
+            }
+        }
+    }
+    Ok(ExitCode::differs(left != right))
+}
+
+/// Serializes `value`, indented if `pretty` is set and on one line otherwise.
+fn to_json(value: &impl Serialize, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+/// Exit statuses shared by all subcommands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ExitCode {
+    Success = 0,
+    Differs = 1,
+    Error = 2,
+    Timeout = 124,
+}
+
+impl ExitCode {
+    const ALL: [Self; 4] = [Self::Success, Self::Differs, Self::Error, Self::Timeout];
+
+    fn differs(differs: bool) -> Self {
+        if differs {
+            Self::Differs
+        } else {
+            Self::Success
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Success => "inputs are equal, or all test cases passed",
+            Self::Differs => "inputs differ, or some test cases failed",
+            Self::Error => "invalid usage, or an input could not be read or run",
+            Self::Timeout => "a time limit was exceeded",
+        }
+    }
+
+    /// The exit status for a run that failed with `err`.
+    fn for_error(err: &anyhow::Error) -> Self {
+        match err.downcast_ref() {
+            Some(DiffError::Timeout { .. }) => Self::Timeout,
+            _ => Self::Error,
+        }
+    }
+
+    /// One line per exit status with its meaning.
+    fn legend() -> String {
+        let mut legend = String::new();
+        for code in Self::ALL {
+            legend.extend([format!("{:>3}  {}\n", code as u8, code.description())]);
+        }
+        legend
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        Self::from(code as u8)
+    }
+}
+
+/// Logs to stderr at the level given by `RUST_LOG`, or else by the number
+/// of `-v` flags.
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+fn main() -> std::process::ExitCode {
+    #[cfg(target_os = "linux")]
+    if std::env::args_os().nth(1).as_deref() == Some(OsStr::new(CLIPBOARD_OWNER_ARG)) {
+        return match serve_clipboard() {
+            Ok(()) => ExitCode::Success.into(),
+            Err(_) => ExitCode::Error.into(),
+        };
+    }
+    let cli = Cli::parse();
+    init_logging(cli.verbose);
+    theme::set(cli.theme);
+
+    if cli.print_exit_codes {
+        print!("{}", ExitCode::legend());
+        return ExitCode::Success.into();
+    }
+    let Some(command) = cli.command else {
+        Cli::command()
+            .error(ErrorKind::MissingSubcommand, "a subcommand is required")
+            .exit();
+    };
+    match run(command) {
+        Ok(code) => code.into(),
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::for_error(&err).into()
+        }
+    }
+}
+
+/// Runs `program` once with the args and input of `case`, printing its
+/// output if it is `expected` and the diff otherwise.
+fn expect(
+    out: &mut impl Write,
+    program: &str,
+    case: &TestCase,
+    expected: &str,
+) -> Result<ExitCode> {
+    let output = spawn_program(
+        program,
+        case.args.as_deref().unwrap_or_default(),
+        case.input.as_deref().as_slice(),
+        &BTreeMap::new(),
+        None,
+        None,
+        false,
+        DEFAULT_SPAWN_RETRIES,
+    )?;
+    let actual = String::from_utf8_lossy(&output.stdout);
+    let (status, report) = compare_outputs(
+        case,
+        case.note(),
+        expected,
+        &actual,
+        expected == actual,
+        DiffOptions::default(),
+    )?;
+    write!(out, "{report}")?;
+    Ok(ExitCode::differs(status != Outcome::Passed))
+}
+
+/// Runs `program` with each of the two argument strings and the same input,
+/// printing the diff from the first output to the second.
+fn self_diff(
+    out: &mut impl Write,
+    program: &str,
+    args: [&str; 2],
+    input: Option<&str>,
+) -> Result<ExitCode> {
+    let run = |args: &str| -> Result<String> {
+        let output = spawn_program(
+            program,
+            args,
+            input.as_slice(),
+            &BTreeMap::new(),
+            None,
+            None,
+            false,
+            DEFAULT_SPAWN_RETRIES,
+        )?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let (left, right) = (run(args[0])?, run(args[1])?);
+    writeln!(out, "{}", DiffOptions::default().diff(&left, &right)?)?;
+    Ok(ExitCode::differs(left != right))
+}
+
+fn run(command: Commands) -> Result<ExitCode> {
+    match command {
+        Commands::Diff(args) => return run_diff(&mut std::io::stdout().lock(), *args),
+        Commands::Differential {
+            reference,
+            candidate,
+            test_file,
+        } => {
+            return TestRunner::new(&reference, &test_file, RunOptions::cli_defaults())?
+                .run_differential(&mut std::io::stdout().lock(), &candidate);
+        }
+        Commands::Example => {
+            println!("{EXAMPLE_STRING}");
+        }
+        Commands::Expect {
+            program,
+            expected,
+            args,
+            input,
+        } => {
+            let case = TestCase {
+                note: Some(program.clone()),
+                args,
+                input,
+                ..TestCase::default()
+            };
+            return expect(&mut std::io::stdout().lock(), &program, &case, &expected);
+        }
+        Commands::Fmt {
+            test_file,
+            in_place,
+        } => {
+            let formatted = serde_yaml::to_string(&TestSuite::load(&test_file)?)?;
+            if in_place {
+                std::fs::write(&test_file, formatted).context("Failed to write test file")?;
+            } else {
+                print!("{formatted}");
+            }
+        }
+        Commands::SelfDiff {
+            program,
+            left_args,
+            right_args,
+            input,
+        } => {
+            return self_diff(
+                &mut std::io::stdout().lock(),
+                &program,
+                [&left_args, &right_args],
+                input.as_deref(),
+            );
+        }
+        Commands::Schema => {
+            let schema = schemars::schema_for!(TestSuite);
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+        }
+    }
+    Ok(ExitCode::Success)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes an executable shell script into `dir`.
+    fn script(dir: &Path, name: &str, body: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        path
+    }
+
+    /// A runner of the suite `yaml` against the shell script `program`,
+    /// both written to `dir`.
+    fn runner(dir: &Path, program: &str, yaml: &str, options: RunOptions) -> TestRunner {
+        let program = script(dir, "program", program);
+        let test_file = dir.join("tests.yaml");
+        std::fs::write(&test_file, yaml).unwrap();
+        TestRunner::new(
+            program.to_str().unwrap(),
+            test_file.to_str().unwrap(),
+            options,
+        )
+        .unwrap()
+    }
+
+    /// Runs the `diff` subcommand with `args`, returning its exit code and
+    /// its output without colors.
+    fn run_cli(args: &[&str]) -> (ExitCode, String) {
+        let mut out = Vec::new();
+        let code = run_diff(&mut out, diff_args(args)).unwrap();
+        (code, strip(&String::from_utf8(out).unwrap()))
+    }
+
+    /// Parses the arguments of the `diff` subcommand.
+    fn diff_args(args: &[&str]) -> DiffArgs {
+        let cli = Cli::try_parse_from(["diff", "diff"].iter().chain(args)).unwrap();
+        let Some(Commands::Diff(args)) = cli.command else {
+            unreachable!("the subcommand is diff");
+        };
+        *args
+    }
+
+    /// Removes the colors from `text`.
+    fn strip(text: &str) -> String {
+        let styles = Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        styles.replace_all(text, "").into_owned()
+    }
+
+    /// Writes files with the given paths and contents below `dir`.
+    fn tree(dir: &Path, files: &[(&str, &str)]) {
+        for (path, text) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+    }
+
+    #[test]
+    fn edit_runs_the_editor_on_a_file_with_the_diff() {
+        edit("true", "-old\n+new\n").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let copy = dir.path().join("copy");
+        let editor = script(
+            dir.path(),
+            "editor",
+            &format!("cp \"$1\" '{}' && echo \"$1\" > '{0}.path'", copy.display()),
+        );
+        edit(&editor.display().to_string(), "-old\n+new\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&copy).unwrap(), "-old\n+new\n");
+        let path = std::fs::read_to_string(format!("{}.path", copy.display())).unwrap();
+        let path = Path::new(path.trim());
+        assert!(
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("diff-")
+        );
+        assert!(!path.exists(), "the temporary file is removed");
+    }
+
+    #[test]
+    fn edit_fails_if_the_editor_does() {
+        assert!(edit("false", "diff").is_err());
+    }
+
+    #[test]
+    fn bench_times_every_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- input: a\n- input: b\n";
+        let runner = runner(dir.path(), "cat", yaml, RunOptions::default());
+        for (_, case) in runner.test_cases.cases() {
+            let timings = runner.bench_test_case(case, 3).unwrap();
+            assert!(timings.min > Duration::ZERO);
+            assert!(timings.min <= timings.median && timings.median <= timings.max);
+        }
+    }
+
+    #[cfg(feature = "clipboard-test")]
+    #[test]
+    fn clipboard_returns_the_text_set() {
+        let text = format!("diff clipboard test {}", std::process::id());
+        #[cfg(target_os = "linux")]
+        let owner = {
+            let text = text.clone();
+            let deadline = Instant::now() + Duration::from_secs(2);
+            std::thread::spawn(move || hold_clipboard(&text, Some(deadline)))
+        };
+        #[cfg(target_os = "linux")]
+        std::thread::sleep(Duration::from_millis(200));
+        #[cfg(not(target_os = "linux"))]
+        copy_to_clipboard(&text).unwrap();
+
+        let mut clipboard = arboard::Clipboard::new().unwrap();
+        assert_eq!(clipboard.get_text().unwrap(), text);
+        #[cfg(target_os = "linux")]
+        owner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn timing_histogram_counts_cases_per_bucket() {
+        let outcomes = [1, 5, 50, 999, 1000, 3000].map(|millis| CaseOutcome {
+            status: Outcome::Passed,
+            duration: Duration::from_millis(millis),
+            actual_output: None,
+        });
+        assert_eq!(timing_histogram(&outcomes), [2, 1, 1, 2]);
+    }
+
+    #[test]
+    fn ref_command_output_is_the_expected_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- ref_command: echo foo\n";
+        let exact = runner(dir.path(), "printf foo", yaml, RunOptions::default());
+        let (_, case) = exact.test_cases.cases().next().unwrap();
+        let (outcome, _) = exact.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Failed, "echo adds a newline");
+
+        let options = RunOptions {
+            normalization: Normalization {
+                trim: true,
+                ..Normalization::default()
+            },
+            ..RunOptions::default()
+        };
+        let trimmed = runner(dir.path(), "printf foo", yaml, options);
+        let (outcome, _) = trimmed.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed);
+    }
+
+    #[test]
+    fn placeholders_resolve_from_suite_vars() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "vars:\n  NAME: world\ntests:\n- args: hello {{NAME}}\n  out: '{{ NAME }}'\n";
+        let runner = runner(dir.path(), "echo \"$@\"", yaml, RunOptions::default());
+        let (_, case) = runner.test_cases.cases().next().unwrap();
+        let case = runner.resolve(case).unwrap();
+        assert_eq!(case.args.as_deref(), Some("hello world"));
+        assert_eq!(case.out.as_deref(), Some("world"));
+
+        let unknown = TestCase {
+            args: Some("{{MISSING_DIFF_TEST_VAR}}".to_owned()),
+            ..TestCase::default()
+        };
+        assert!(runner.resolve(&unknown).is_err());
+    }
+
+    #[test]
+    fn stat_ends_with_totals_over_both_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        tree(&left, &[("same", "x\n"), ("changed", "a\nb\n")]);
+        tree(
+            &right,
+            &[("same", "x\n"), ("changed", "a\nc\n"), ("new", "d\n")],
+        );
+        let (code, out) = run_cli(&[
+            "-m",
+            "file",
+            "--stat",
+            left.to_str().unwrap(),
+            right.to_str().unwrap(),
+        ]);
+        assert_eq!(code, ExitCode::Differs);
+        assert_eq!(
+            out,
+            " changed | 2 +-\n new     | 1 +\n 2 files changed, 2 insertions(+), 1 deletion(-), changed 42% (6/14 chars)\n"
+        );
+    }
+
+    #[test]
+    fn env_file_sets_variables_for_the_program() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("suite.env"), "GREETING=hello\nNAME=file\n").unwrap();
+        let yaml = "env_file: suite.env\ntests:\n- out: \"hello file\\n\"\n- env: {NAME: case}\n  out: \"hello case\\n\"\n";
+        let runner = runner(
+            dir.path(),
+            "echo \"$GREETING $NAME\"",
+            yaml,
+            RunOptions::default(),
+        );
+        for (_, case) in runner.test_cases.cases() {
+            let (outcome, report) = runner.run_test_case(case, None).unwrap();
+            assert_eq!(outcome.status, Outcome::Passed, "{report}");
+        }
+    }
+
+    #[test]
+    fn header_names_both_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("old.txt"), dir.path().join("new.txt"));
+        std::fs::write(&left, "a\n").unwrap();
+        std::fs::write(&right, "b\n").unwrap();
+        let (left, right) = (left.to_str().unwrap(), right.to_str().unwrap());
+
+        let (_, out) = run_cli(&["-m", "file", "-f", "unified", left, right]);
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some(format!("--- {left}").as_str()));
+        assert_eq!(lines.next(), Some(format!("+++ {right}").as_str()));
+
+        let (_, out) = run_cli(&["-m", "file", "-f", "unified", "--no-header", left, right]);
+        assert!(out.starts_with("@@"), "{out}");
+
+        let header = file_header(left, right, true).unwrap();
+        let timed = Regex::new(r"^\S+ \S+\t\d{4}-\d\d-\d\dT\d\d:\d\d:\d\dZ$").unwrap();
+        assert!(
+            header.lines().all(|line| timed.is_match(&strip(line))),
+            "{header}"
+        );
+    }
+
+    #[test]
+    fn gzipped_file_equals_its_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let (plain, gzipped) = (dir.path().join("log"), dir.path().join("log.gz"));
+        std::fs::write(&plain, "line 1\nline 2\n").unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&gzipped).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"line 1\nline 2\n").unwrap();
+        encoder.finish().unwrap();
+        let (plain, gzipped) = (plain.to_str().unwrap(), gzipped.to_str().unwrap());
+
+        let (code, _) = run_cli(&["-m", "file", plain, gzipped]);
+        assert_eq!(code, ExitCode::Success);
+        let raw = ReadOptions {
+            decompress: false,
+            max_size: None,
+            timeout: None,
+            encoding: encoding_rs::UTF_8,
+        };
+        assert!(read_input(gzipped, raw).is_err(), "gzip data is not UTF-8");
+    }
+
+    #[test]
+    fn differential_fails_the_cases_where_outputs_differ() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- args: one\n- args: two\n- args: three\n";
+        let reference = runner(dir.path(), "echo \"$1\"", yaml, RunOptions::default());
+        let candidate = script(
+            dir.path(),
+            "candidate",
+            "if [ \"$1\" = two ]; then echo 2; else echo \"$1\"; fi",
+        );
+        let mut out = Vec::new();
+        let code = reference
+            .run_differential(&mut out, candidate.to_str().unwrap())
+            .unwrap();
+        assert_eq!(code, ExitCode::Differs);
+        let out = strip(&String::from_utf8(out).unwrap());
+        assert!(out.ends_with("\n2 passed, 1 failed\n"), "{out}");
+
+        let mut out = Vec::new();
+        let code = reference
+            .run_differential(&mut out, dir.path().join("program").to_str().unwrap())
+            .unwrap();
+        assert_eq!(code, ExitCode::Success);
+    }
+
+    #[test]
+    fn first_difference_reports_where_inputs_diverge() {
+        let report = strip(&first_difference("héllo\nworld\n", "héllo\nworms\n").unwrap());
+        assert_eq!(
+            report,
+            "First difference at byte 10 (line 2, column 4)\n- \"héllo\\nworld\\n\"\n+ \"héllo\\nworms\\n\""
+        );
+        assert_eq!(
+            first_difference("same", "same").unwrap(),
+            "Inputs are identical"
+        );
+    }
+
+    #[test]
+    fn differing_should_fail_case_counts_as_passing() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- out: \"fixed\\n\"\n  should_fail: true\n- out: \"broken\\n\"\n  should_fail: true\n";
+        let runner = runner(dir.path(), "echo broken", yaml, RunOptions::default());
+        let outcomes = runner
+            .test_cases
+            .cases()
+            .map(|(_, case)| runner.run_test_case(case, None).unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes[0].status, Outcome::ExpectedFailure);
+        assert!(outcomes[0].status.is_success());
+        assert_eq!(outcomes[1].status, Outcome::UnexpectedPass);
+        assert!(outcomes[1].status.is_failure());
+        assert_eq!(
+            outcome_counts(&outcomes.iter().collect::<Vec<_>>()),
+            "0 passed, 0 failed, 1 xfail, 1 xpass"
+        );
+    }
+
+    #[test]
+    fn input_errors_are_told_apart_by_variant() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = |max_size| ReadOptions {
+            decompress: true,
+            max_size,
+            timeout: None,
+            encoding: encoding_rs::UTF_8,
+        };
+        let missing = dir.path().join("missing");
+        assert!(matches!(
+            read_input(missing.to_str().unwrap(), options(None)),
+            Err(DiffError::IoError { source, .. }) if source.kind() == std::io::ErrorKind::NotFound
+        ));
+
+        let binary = dir.path().join("binary");
+        std::fs::write(&binary, [0xff, 0xfe, b'\n']).unwrap();
+        let binary = binary.to_str().unwrap();
+        assert!(matches!(
+            read_input(binary, options(None)),
+            Err(DiffError::ParseError { .. })
+        ));
+        assert!(matches!(
+            read_input(binary, options(Some(2))),
+            Err(DiffError::InputTooLarge {
+                size: 3,
+                limit: 2,
+                ..
+            })
+        ));
+
+        let runner = runner(
+            dir.path(),
+            "true",
+            "tests:\n- out: \"\"\n",
+            RunOptions::default(),
+        );
+        let missing = dir.path().join("no-such-program");
+        let (_, case) = runner.test_cases.cases().next().unwrap();
+        assert!(matches!(
+            runner.execute_program(&missing, case),
+            Err(DiffError::SpawnFailed { program, .. }) if program == missing
+        ));
+    }
+
+    #[test]
+    fn summary_counts_skipped_and_filtered_cases() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut yaml = "tests:\n".to_owned();
+        for note in ["a1", "a2", "a3", "a4", "a5", "a6", "b1", "b2", "b3"] {
+            let skip = matches!(note, "a5" | "a6");
+            yaml.extend([format!("- note: {note}\n  out: \"x\\n\"\n  skip: {skip}\n")]);
+        }
+        let options = RunOptions {
+            filter: Some("a".to_owned()),
+            ..RunOptions::default()
+        };
+        let runner = runner(dir.path(), "echo x", &yaml, options);
+        let (cases, selection) = runner.select(&mut std::io::sink()).unwrap();
+        let (groups, cases): (Vec<_>, Vec<_>) = cases.into_iter().unzip();
+        let outcomes = cases
+            .iter()
+            .map(|case| runner.run_test_case(case, None).unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            strip(&summary(&outcomes, &groups, &selection)),
+            "ran 4, skipped 2, filtered 3 (of 9)\n4 passed, 0 failed\n"
+        );
+    }
+
+    #[test]
+    fn out_any_passes_on_any_candidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- out_any: [\"a b\\n\", \"b a\\n\", \"ab\\n\"]\n- out_any: [\"q\\n\", \"b a c\\n\", \"xyz\\n\"]\n";
+        let runner = runner(dir.path(), "echo b a", yaml, RunOptions::default());
+        let mut cases = runner.test_cases.cases();
+        let (_, matching) = cases.next().unwrap();
+        let (outcome, _) = runner.run_test_case(matching, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed);
+
+        let (_, failing) = cases.next().unwrap();
+        let (outcome, report) = runner.run_test_case(failing, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Failed);
+        assert_eq!(
+            closest_match(
+                ["q\n", "b a c\n", "xyz\n"].map(str::to_owned).into_iter(),
+                "b a\n"
+            )
+            .unwrap(),
+            "b a c\n"
+        );
+        assert!(strip(&report).contains("b a c"), "{report}");
+    }
+
+    #[test]
+    fn auto_granularity_switches_to_lines_at_the_threshold() {
+        let options = DiffOptions {
+            granularity_threshold: 100,
+            ..DiffOptions::default()
+        };
+        let (below, at) = ("x".repeat(99), "x".repeat(100));
+        assert_eq!(options.granularity_for(&below, ""), Granularity::Char);
+        assert_eq!(options.granularity_for("", &at), Granularity::Line);
+
+        let char = DiffOptions {
+            granularity: Granularity::Char,
+            ..options
+        };
+        assert_eq!(char.granularity_for(&at, &at), Granularity::Char);
+        let line = DiffOptions {
+            granularity: Granularity::Line,
+            ..options
+        };
+        assert_eq!(line.granularity_for(&below, &below), Granularity::Line);
+    }
+
+    #[test]
+    fn changed_share_is_a_percentage_of_both_inputs() {
+        let summary = compute_diff("kitten", "sitting").unwrap().summary();
+        assert_eq!(summary.changed_share(), "changed 38% (5/13 chars)");
+        assert_eq!(
+            DiffSummary::unchanged("").changed_share(),
+            "changed 0% (0/0 chars)"
+        );
+    }
+
+    #[test]
+    fn stdin_is_split_at_the_first_delimiter_line() {
+        let (left, right) =
+            split_at_delimiter("a\n---DIFF---\nb\n---DIFF---\n", "---DIFF---").unwrap();
+        assert_eq!((left.as_str(), right.as_str()), ("a\n", "b\n---DIFF---\n"));
+        let (left, right) = split_at_delimiter("a\r\n--\r\nb", "--").unwrap();
+        assert_eq!((left.as_str(), right.as_str()), ("a\r\n", "b"));
+        assert!(split_at_delimiter("a ---DIFF--- b\n", "---DIFF---").is_err());
+    }
+
+    #[test]
+    fn schema_describes_tests_and_args() {
+        let schema = serde_json::to_value(schemars::schema_for!(TestSuite)).unwrap();
+        let definitions = &schema["$defs"];
+        let tests = &definitions["Group"]["properties"]["tests"];
+        assert_eq!(tests["items"]["$ref"], "#/$defs/TestCase", "{schema}");
+        assert!(
+            definitions["TestCase"]["properties"]["args"].is_object(),
+            "{schema}"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_output_fails_only_when_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- out: \"ok\\uFFFD\\n\"\n";
+        let lossy = runner(
+            dir.path(),
+            "printf 'ok\\377\\n'",
+            yaml,
+            RunOptions::default(),
+        );
+        let (_, case) = lossy.test_cases.cases().next().unwrap();
+        let (outcome, report) = lossy.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed, "{report}");
+
+        let options = RunOptions {
+            strict_utf8: true,
+            ..RunOptions::default()
+        };
+        let strict = runner(dir.path(), "printf 'ok\\377\\n'", yaml, options);
+        let (_, case) = strict.test_cases.cases().next().unwrap();
+        let (outcome, report) = strict.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Failed);
+        assert!(
+            report.contains("Output is not valid UTF-8 at byte 2"),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn count_only_prints_a_single_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        std::fs::write(&left, "keep\ngone\nold\n").unwrap();
+        std::fs::write(&right, "keep\nnew\nadded\nmore\n").unwrap();
+        let (left, right) = (left.to_str().unwrap(), right.to_str().unwrap());
+        let args = ["-m", "file", "--count-only", "-f"];
+
+        let (code, out) = run_cli(&[&args[..], &["unified", left, right]].concat());
+        assert_eq!(code, ExitCode::Differs);
+        assert_eq!(out, "ins=3 del=2\n");
+        // JSON counts chars rather than lines
+        let typo = dir.path().join("typo");
+        std::fs::write(&typo, "keep\ngone\nolld\n").unwrap();
+        let (_, out) = run_cli(&[&args[..], &["json", left, typo.to_str().unwrap()]].concat());
+        assert_eq!(out, "{\"ins\":1,\"del\":0}\n");
+        let (code, out) = run_cli(&[&args[..], &["unified", left, left]].concat());
+        assert_eq!((code, out.as_str()), (ExitCode::Success, "ins=0 del=0\n"));
+    }
+
+    #[test]
+    fn expect_compares_one_run_with_the_expected_output() {
+        let case = |args: &str| TestCase {
+            note: Some("echo".to_owned()),
+            args: Some(args.to_owned()),
+            ..TestCase::default()
+        };
+        let mut out = Vec::new();
+        assert_eq!(
+            expect(&mut out, "echo", &case("hi"), "hi\n").unwrap(),
+            ExitCode::Success
+        );
+        assert_eq!(strip(&String::from_utf8(out).unwrap()), "echo\nhi\n\n");
+        let mut out = Vec::new();
+        assert_eq!(
+            expect(&mut out, "echo", &case("ho"), "hi\n").unwrap(),
+            ExitCode::Differs
+        );
+    }
+
+    /// Collects the messages of events, tagged with the span they were
+    /// emitted in.
+    #[derive(Clone, Default)]
+    struct Events(std::sync::Arc<Mutex<Vec<String>>>);
+
+    impl<S> tracing_subscriber::Layer<S> for Events
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            context: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            struct Message(String);
+            impl tracing::field::Visit for Message {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    if field.name() == "message" {
+                        self.0 = format!("{value:?}");
+                    }
+                }
+            }
+            let mut message = Message(String::new());
+            event.record(&mut message);
+            let span = context
+                .event_span(event)
+                .map(|span| span.name())
+                .unwrap_or_default();
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{span}: {}", message.0));
+        }
+    }
+
+    #[test]
+    fn running_a_case_emits_tracing_events() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let runner = runner(
+            dir.path(),
+            "cat",
+            "tests:\n- input: hi\n  out: hi\n",
+            RunOptions::default(),
+        );
+        let (_, case) = runner.test_cases.cases().next().unwrap();
+        let events = Events::default();
+        let subscriber = tracing_subscriber::registry().with(events.clone());
+        tracing::subscriber::with_default(subscriber, || runner.run_test_case(case, None).unwrap());
+
+        let events = events.0.lock().unwrap();
+        for expected in [
+            "run_test_case: spawning program",
+            "run_test_case: writing stdin",
+            "run_test_case: waiting for program",
+            "run_test_case: program exited",
+            "run_test_case: case finished",
+        ] {
+            assert!(events.iter().any(|event| event == expected), "{events:?}");
+        }
+    }
+
+    /// The text of every segment of `diff` with the given op.
+    fn segments(diff: &DiffVec, op: Ops) -> Vec<String> {
+        diff.0
+            .iter()
+            .filter(|segment| segment.op() == op)
+            .map(|segment| segment.data().iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn refine_highlights_only_the_changed_word() {
+        let options = DiffOptions {
+            granularity: Granularity::Line,
+            refine: true,
+            ..DiffOptions::default()
+        };
+        let diff = options
+            .diff(
+                "same\nthe quick brown fox jumps\n",
+                "same\nthe lazy brown fox jumps\n",
+            )
+            .unwrap();
+        assert_eq!(segments(&diff, Ops::Delete), ["quick"]);
+        assert_eq!(segments(&diff, Ops::Insert), ["lazy"]);
+
+        let unrefined = DiffOptions {
+            refine: false,
+            ..options
+        };
+        let diff = unrefined
+            .diff(
+                "same\nthe quick brown fox jumps\n",
+                "same\nthe lazy brown fox jumps\n",
+            )
+            .unwrap();
+        assert_eq!(
+            segments(&diff, Ops::Delete),
+            ["the quick brown fox jumps\n"]
+        );
+    }
+
+    #[test]
+    fn inputs_over_the_size_limit_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let (small, large) = (dir.path().join("small"), dir.path().join("large"));
+        std::fs::write(&small, "tiny\n").unwrap();
+        std::fs::write(&large, "0123456789\n").unwrap();
+        let (small, large) = (small.to_str().unwrap(), large.to_str().unwrap());
+
+        let args = diff_args(&["-m", "file", "--max-input-size", "8", small, large]);
+        let err = run_diff(&mut Vec::new(), args).unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref(),
+                Some(DiffError::InputTooLarge {
+                    size: 11,
+                    limit: 8,
+                    ..
+                })
+            ),
+            "{err:?}"
+        );
+
+        let (code, _) = run_cli(&[
+            "-m",
+            "file",
+            "--max-input-size",
+            "8",
+            "--force-large",
+            small,
+            large,
+        ]);
+        assert_eq!(code, ExitCode::Differs);
+    }
+
+    #[test]
+    fn fmt_writes_a_canonical_suite() {
+        let dir = tempfile::tempdir().unwrap();
+        let messy = dir.path().join("messy.yaml");
+        std::fs::write(
+            &messy,
+            "tests:\n  - out: \"hi\\n\"\n    args:   '-n hi'\n    note: greeting\n    env: {}\n  - {input: x, out: x}\n",
+        )
+        .unwrap();
+        let formatted = serde_yaml::to_string(&TestSuite::load(&messy).unwrap()).unwrap();
+        assert_eq!(
+            formatted,
+            "tests:\n- note: greeting\n  args: -n hi\n  out: |\n    hi\n- input: x\n  out: x\n"
+        );
+        let canonical = dir.path().join("canonical.yaml");
+        std::fs::write(&canonical, &formatted).unwrap();
+        let again = serde_yaml::to_string(&TestSuite::load(&canonical).unwrap()).unwrap();
+        assert_eq!(again, formatted);
+    }
+
+    #[test]
+    fn input_command_output_is_the_program_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- input_command: echo 42\n  out: \"got 42\\n\"\n- input_command: \"false\"\n  out: \"got \\n\"\n";
+        let runner = runner(
+            dir.path(),
+            "read n; echo \"got $n\"",
+            yaml,
+            RunOptions::default(),
+        );
+        let mut cases = runner.test_cases.cases();
+        let (_, case) = cases.next().unwrap();
+        let (outcome, report) = runner.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed, "{report}");
+        let (_, failing) = cases.next().unwrap();
+        let (outcome, report) = runner.run_test_case(failing, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Failed);
+        assert!(
+            report.contains("Input command \"false\" failed"),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn longest_equal_run_is_the_largest_unchanged_segment() {
+        let diff = compute_diff("abcXdefghYij", "abcZdefghWij").unwrap();
+        assert_eq!(diff.longest_equal_run(false), "defgh".len());
+        let diff =
+            compute_line_diff("a\nb\nc\nd\ne\n", "a\nB\nc\nd\ne\n", DEFAULT_DIFF_TIMEOUT).unwrap();
+        assert_eq!(diff.longest_equal_run(true), 3);
+        assert_eq!(
+            compute_diff("ab", "cd").unwrap().longest_equal_run(false),
+            0
+        );
+    }
+
+    #[test]
+    fn every_outcome_has_its_style() {
+        for (outcome, style) in [
+            (Outcome::Passed, STYLE_PASSED),
+            (Outcome::ExpectedFailure, STYLE_PASSED),
+            (Outcome::Failed, STYLE_DELETED),
+            (Outcome::UnexpectedPass, STYLE_DELETED),
+            (Outcome::Skipped, STYLE_INSERTED),
+            (Outcome::NotRun, STYLE_INSERTED),
+        ] {
+            assert_eq!(outcome.style(), style.style(), "{outcome:?}");
+            let header = case_header(outcome, "note");
+            assert!(
+                header.starts_with(&style.style().render().to_string()),
+                "{outcome:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn line_ranges_slice_and_clamp() {
+        let range = |range| LineRange::parse(range).unwrap();
+        let text = "1\n2\n3\n4\n5\n";
+        assert_eq!(range("2:3").slice(text), "2\n3\n");
+        assert_eq!(range(":2").slice(text), "1\n2\n");
+        assert_eq!(range("4:").slice(text), "4\n5\n");
+        assert_eq!(range("4:99").slice(text), "4\n5\n");
+        assert_eq!(range("9:12").slice(text), "");
+        assert_eq!(range("3:1").slice(text), "");
+        assert!(LineRange::parse("2-3").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        std::fs::write(&left, "a\nb\nc\nd\ne\n").unwrap();
+        std::fs::write(&right, "A\nb\nc\nD\nE\n").unwrap();
+        let (left, right) = (left.to_str().unwrap(), right.to_str().unwrap());
+        let ranges = ["--left-range", "2:3", "--right-range", "2:3"];
+        let (code, _) = run_cli(&[&["-m", "file"][..], &ranges, &[left, right]].concat());
+        assert_eq!(code, ExitCode::Success);
+    }
+
+    #[test]
+    fn summary_only_prints_no_case_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- note: same\n  out: \"x\\n\"\n- note: other\n  out: \"y\\n\"\n- note: later\n  skip: true\n";
+        let report = dir.path().join("report.txt");
+        let options = RunOptions {
+            summary_only: true,
+            report: Some(report.clone()),
+            ..RunOptions::default()
+        };
+        let runner = runner(dir.path(), "echo x", yaml, options);
+        let mut out = Vec::new();
+        assert_eq!(runner.run_to(&mut out).unwrap(), ExitCode::Differs);
+        let summary = "ran 2, skipped 1, filtered 0 (of 3)\n1 passed, 1 failed\n";
+        assert_eq!(strip(&String::from_utf8(out).unwrap()), summary);
+        assert_eq!(strip(&std::fs::read_to_string(report).unwrap()), summary);
+    }
+
+    #[test]
+    fn cached_outputs_are_reused_while_the_program_is_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("cache");
+        // Fails every run after the first
+        let body = format!(
+            "[ -e '{0}' ] && exit 1; touch '{0}'; echo \"out $1\"",
+            dir.path().join("ran").display()
+        );
+        let options = RunOptions {
+            cache: Some(cache.clone()),
+            ..RunOptions::default()
+        };
+        let runner = runner(
+            dir.path(),
+            &body,
+            "tests:\n- args: a\n  out: \"out a\\n\"\n",
+            options,
+        );
+        let (_, case) = runner.test_cases.cases().next().unwrap();
+        for _ in 0..2 {
+            let (outcome, report) = runner.run_test_case(case, None).unwrap();
+            assert_eq!(outcome.status, Outcome::Passed, "{report}");
+        }
+        let entries = std::fs::read_dir(&cache)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].len(), 64 + ".out".len(), "{entries:?}");
+
+        let other = TestCase {
+            args: Some("b".to_owned()),
+            ..case.clone()
+        };
+        let (outcome, _) = runner.run_test_case(&other, None).unwrap();
+        assert_eq!(
+            outcome.status,
+            Outcome::Failed,
+            "a new key runs the program"
+        );
+    }
+
+    #[test]
+    fn anchored_env_is_merged_into_cases() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "\
+shared: &shared
+  env: {GREETING: hello, NAME: world}
+tests:
+- <<: *shared
+  out: \"hello world\\n\"
+- <<: *shared
+  env: {GREETING: bye}
+  out: \"bye \\n\"
+";
+        let runner = runner(
+            dir.path(),
+            "echo \"$GREETING $NAME\"",
+            yaml,
+            RunOptions::default(),
+        );
+        for (_, case) in runner.test_cases.cases() {
+            let (outcome, report) = runner.run_test_case(case, None).unwrap();
+            assert_eq!(outcome.status, Outcome::Passed, "{report}");
+        }
+    }
+
+    #[test]
+    fn confirmed_updates_follow_the_answers() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut yaml = "tests:\n".to_owned();
+        for note in ["a", "b", "c", "d", "e"] {
+            yaml.extend([format!("- note: {note}\n  args: {note}\n  out: old\n")]);
+        }
+        let runner = runner(dir.path(), "echo \"$1\"", &yaml, RunOptions::default());
+        let results = runner
+            .test_cases
+            .cases()
+            .map(|(_, case)| (case, runner.run_test_case(case, None).unwrap()))
+            .collect::<Vec<_>>();
+        let results = results
+            .iter()
+            .map(|(case, (outcome, report))| (*case, (outcome, report)));
+        // Skip a, update b, then update all remaining ones
+        let mut answers = ["n", "y", "a"].into_iter();
+        let mut out = Vec::new();
+        runner
+            .update(&mut out, results, Update::Confirm, |question| {
+                assert_eq!(question, "update? [y/N/a/q] ");
+                Ok(answers
+                    .next()
+                    .expect("no more questions after a")
+                    .to_owned())
+            })
+            .unwrap();
+        let suite = TestSuite::load(dir.path().join("tests.yaml")).unwrap();
+        let outs = suite
+            .cases()
+            .map(|(_, case)| case.out.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(outs, ["old", "b\n", "c\n", "d\n", "e\n"]);
+        let out = strip(&String::from_utf8(out).unwrap());
+        let updated = format!("Updated 4 case(s) in {}\n", runner.test_file.display());
+        assert!(out.ends_with(&updated), "{out}");
+
+        let results = runner
+            .test_cases
+            .cases()
+            .map(|(_, case)| case)
+            .collect::<Vec<_>>();
+        let outcomes = results
+            .iter()
+            .map(|case| runner.run_test_case(case, None).unwrap())
+            .collect::<Vec<_>>();
+        let mut answers = ["q"].into_iter();
+        runner
+            .update(
+                &mut Vec::new(),
+                results
+                    .into_iter()
+                    .zip(outcomes.iter().map(|(outcome, report)| (outcome, report))),
+                Update::Confirm,
+                |_| Ok(answers.next().unwrap().to_owned()),
+            )
+            .unwrap();
+        assert!(answers.next().is_none(), "q stops asking");
+    }
+
+    #[test]
+    fn engines_agree_on_ascii_input() {
+        let (left, right) = (
+            "The quick brown fox\njumps over\n",
+            "The quack brown cat\njumped over\n",
+        );
+        let ops = |engine: Engine| {
+            let diff = engine.diff(left, right, DEFAULT_DIFF_TIMEOUT).unwrap();
+            diff.0
+                .iter()
+                .map(|segment| (segment.op(), segment.data().iter().collect::<String>()))
+                .collect::<Vec<_>>()
+        };
+        let compat = ops(Engine::Compat);
+        assert_eq!(compat, ops(Engine::Efficient));
+        let side = |op| {
+            compat
+                .iter()
+                .filter(|(segment, _)| *segment != op)
+                .map(|(_, text)| text.as_str())
+                .collect::<String>()
+        };
+        assert_eq!(
+            (side(Ops::Insert), side(Ops::Delete)),
+            (left.to_owned(), right.to_owned())
+        );
+    }
+
+    #[test]
+    fn timed_note_shows_the_delta_from_the_average() {
+        let average = Some(Duration::from_millis(34));
+        assert_eq!(
+            timed_note("test", Duration::from_millis(42), average),
+            "test (42ms, +8ms vs avg)"
+        );
+        assert_eq!(
+            timed_note("test", Duration::from_millis(30), average),
+            "test (30ms, -4ms vs avg)"
+        );
+        assert_eq!(
+            timed_note("test", Duration::from_millis(30), None),
+            "test (30ms)"
+        );
+    }
+
+    #[test]
+    fn first_differing_line_stops_reading_at_the_difference() {
+        let dir = tempfile::tempdir().unwrap();
+        let lines = |changed: &'static str| {
+            (1..=100_000).map(|n| {
+                if n == 3 {
+                    changed.to_owned()
+                } else {
+                    format!("line {n}")
+                }
+            })
+        };
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        std::fs::write(&left, lines("old").collect::<Vec<_>>().join("\n")).unwrap();
+        std::fs::write(&right, lines("new").collect::<Vec<_>>().join("\n")).unwrap();
+        let report = first_differing_line(&left, &right).unwrap().unwrap();
+        assert_eq!(
+            strip(&report),
+            "First difference at line 3\n- \"old\"\n+ \"new\""
+        );
+        assert_eq!(first_differing_line(&left, &left).unwrap(), None);
+
+        let read = std::cell::Cell::new(0);
+        let counted = |changed| lines(changed).map(Ok).inspect(|_| read.set(read.get() + 1));
+        first_differing_line_of(counted("old"), counted("new")).unwrap();
+        assert_eq!(read.get(), 2 * 3);
+    }
+
+    #[test]
+    fn show_inputs_prints_both_inputs_before_the_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        std::fs::write(&left, "one\ntwo\n").unwrap();
+        std::fs::write(&right, "one\nthree\n").unwrap();
+        let (left, right) = (left.to_str().unwrap(), right.to_str().unwrap());
+        let (_, out) = run_cli(&[
+            "-m",
+            "file",
+            "-f",
+            "unified",
+            "--no-header",
+            "--show-inputs",
+            left,
+            right,
+        ]);
+        assert_eq!(
+            out,
+            "Left input:\none\ntwo\n\nRight input:\none\nthree\n\n@@ -1,2 +1,2 @@\n one\n-two\n+three\n"
+        );
+    }
+
+    #[test]
+    fn cases_share_the_suite_out_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("expected")).unwrap();
+        std::fs::write(dir.path().join("expected/usage.txt"), "usage: program\n").unwrap();
+        std::fs::write(dir.path().join("own.txt"), "own\n").unwrap();
+        let yaml = "out_file: expected/usage.txt\ntests:\n- args: -h\n- args: --help\n- args: own\n  out_file: own.txt\n- args: inline\n  out: \"inline\\n\"\n";
+        let program = "case \"$1\" in -h|--help) echo 'usage: program' ;; *) echo \"$1\" ;; esac";
+        let runner = runner(dir.path(), program, yaml, RunOptions::default());
+        for (_, case) in runner.test_cases.cases() {
+            let (outcome, report) = runner.run_test_case(case, None).unwrap();
+            assert_eq!(outcome.status, Outcome::Passed, "{report}");
+        }
+    }
+
+    #[test]
+    fn wrap_breaks_long_lines_and_restarts_the_style() {
+        let equal = "a".repeat(25);
+        let diff =
+            compute_diff(&format!("{equal}x\nshort\n"), &format!("{equal}y\nshort\n")).unwrap();
+        assert_eq!(
+            strip(&diff.render(None, Some(10))),
+            "aaaaaaaaaa\naaaaaaaaaa\naaaaaxy\nshort\n"
+        );
+
+        let inserted = "b".repeat(15);
+        let diff = compute_diff("", &inserted).unwrap();
+        let inserted = STYLE_INSERTED.style();
+        assert_eq!(
+            diff.render(None, Some(10)),
+            format!("{inserted}bbbbbbbbbb{inserted:#}\n{inserted}bbbbb{inserted:#}")
+        );
+    }
+
+    #[test]
+    fn trailing_space_difference_becomes_visible() {
+        let diff = compute_diff("key value\n", "key value \n").unwrap();
+        assert_eq!(strip(&diff.to_string()), "key value \n");
+        let rendered = diff.render(Some(Whitespace::Changed), None);
+        assert_eq!(strip(&rendered), "key value·\n");
+        let trailing = STYLE_DELETED.style();
+        assert!(rendered.contains(&format!("{trailing}·")), "{rendered:?}");
+
+        let diff = compute_diff("a b\tc\n", "a b\tC\n").unwrap();
+        assert_eq!(
+            strip(&diff.render(Some(Whitespace::Changed), None)),
+            "a b\tcC\n"
+        );
+        assert_eq!(strip(&diff.render(Some(Whitespace::All), None)), "a·b→cC\n");
+    }
+
+    #[test]
+    fn self_diff_compares_two_runs_of_one_program() {
+        let mut out = Vec::new();
+        let code = self_diff(&mut out, "printf", ["a-%s-b x", "a-%s-c x"], None).unwrap();
+        assert_eq!(code, ExitCode::Differs);
+        let inserted = STYLE_INSERTED.style();
+        let deleted = STYLE_DELETED.style();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("a-x-{deleted}b{deleted:#}{inserted}c{inserted:#}\n")
+        );
+
+        let code = self_diff(&mut Vec::new(), "cat", ["-", "-"], Some("same\n")).unwrap();
+        assert_eq!(code, ExitCode::Success);
+    }
+
+    #[test]
+    fn exit_codes_map_each_scenario() {
+        let dir = tempfile::tempdir().unwrap();
+        let (same, other) = (dir.path().join("same"), dir.path().join("other"));
+        std::fs::write(&same, "a\n").unwrap();
+        std::fs::write(&other, "b\n").unwrap();
+        let (same, other) = (same.to_str().unwrap(), other.to_str().unwrap());
+        assert_eq!(run_cli(&["-m", "file", same, same]).0, ExitCode::Success);
+        assert_eq!(run_cli(&["-m", "file", same, other]).0, ExitCode::Differs);
+
+        let missing = dir.path().join("missing").display().to_string();
+        let err = expect(&mut Vec::new(), &missing, &TestCase::default(), "").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(DiffError::SpawnFailed { .. })
+        ));
+        assert_eq!(ExitCode::for_error(&err), ExitCode::Error);
+        let timeout = anyhow::Error::from(DiffError::Timeout {
+            program: PathBuf::from("program"),
+        });
+        assert_eq!(ExitCode::for_error(&timeout), ExitCode::Timeout);
+
+        let options = RunOptions {
+            suite_timeout: Some(Duration::from_millis(100)),
+            ..RunOptions::default()
+        };
+        let runner = runner(dir.path(), "sleep 5", "tests:\n- out: \"\"\n", options);
+        assert_eq!(runner.run_to(std::io::sink()).unwrap(), ExitCode::Timeout);
+
+        assert_eq!(
+            ExitCode::legend()
+                .lines()
+                .map(|line| &line[..3])
+                .collect::<Vec<_>>(),
+            ["  0", "  1", "  2", "124"]
+        );
+    }
+
+    #[test]
+    fn rerun_runs_only_the_failures_of_the_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("report.json");
+        std::fs::write(
+            &report,
+            r#"{"cases": [
+                {"note": "one", "outcome": "passed", "duration_ms": 1},
+                {"note": "two", "outcome": "failed", "duration_ms": 1},
+                {"note": "three", "outcome": "passed", "duration_ms": 1}
+            ]}"#,
+        )
+        .unwrap();
+        let options = RunOptions {
+            rerun: Some(Report::load(&report).unwrap().failures()),
+            ..RunOptions::default()
+        };
+        let log = dir.path().join("ran");
+        let program = format!("echo \"$1\" >> '{}'; echo \"$1\"", log.display());
+        let mut yaml = "tests:\n".to_owned();
+        for note in ["one", "two", "three"] {
+            yaml.extend([format!(
+                "- note: {note}\n  args: {note}\n  out: \"{note}\\n\"\n"
+            )]);
+        }
+        let runner = runner(dir.path(), &program, &yaml, options);
+        assert_eq!(runner.run_to(std::io::sink()).unwrap(), ExitCode::Success);
+        assert_eq!(std::fs::read_to_string(log).unwrap(), "two\n");
+    }
+
+    #[test]
+    fn environment_variables_expand_in_args() {
+        // Cargo sets this for test runs
+        let name = env!("CARGO_PKG_NAME");
+        assert_eq!(
+            expand_env("$CARGO_PKG_NAME-${CARGO_PKG_NAME}_ $$5 $", false).unwrap(),
+            format!("{name}-{name}_ $5 $")
+        );
+        assert_eq!(expand_env("[$DIFF_TEST_UNSET]", false).unwrap(), "[]");
+        assert!(expand_env("$DIFF_TEST_UNSET", true).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = format!("tests:\n- args: $CARGO_PKG_NAME\n  out: \"{name}\\n\"\n");
+        let options = RunOptions {
+            expand_env: Some(false),
+            ..RunOptions::default()
+        };
+        let echo = runner(dir.path(), "echo \"$1\"", &yaml, options);
+        let (_, case) = echo.test_cases.cases().next().unwrap();
+        let (outcome, report) = echo
+            .run_test_case(&echo.resolve(case).unwrap(), None)
+            .unwrap();
+        assert_eq!(outcome.status, Outcome::Passed, "{report}");
+
+        std::fs::create_dir(dir.path().join(name)).unwrap();
+        std::fs::write(dir.path().join(name).join("here"), "").unwrap();
+        let yaml = "tests:\n- cwd: $CARGO_PKG_NAME\n  out: \"here\\n\"\n";
+        let options = RunOptions {
+            expand_env: Some(false),
+            ..RunOptions::default()
+        };
+        let listing = runner(dir.path(), "ls", yaml, options);
+        let (_, case) = listing.test_cases.cases().next().unwrap();
+        let (outcome, report) = listing
+            .run_test_case(&listing.resolve(case).unwrap(), None)
+            .unwrap();
+        assert_eq!(outcome.status, Outcome::Passed, "{report}");
+    }
+
+    /// Serves one canned HTTP response per connection on a local port,
+    /// returning the base URL.
+    #[cfg(feature = "http")]
+    fn mock_server(responses: Vec<(&'static str, &'static str)>) -> String {
+        use std::io::BufRead;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for (stream, (status, body)) in listener.incoming().zip(responses) {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        url
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn url_input_is_fetched_and_diffed() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = dir.path().join("local.conf");
+        std::fs::write(&local, "port = 80\n").unwrap();
+        let local = local.to_str().unwrap();
+        let url = mock_server(vec![
+            ("200 OK", "port = 80\n"),
+            ("404 Not Found", "missing"),
+        ]);
+
+        let (code, _) = run_cli(&["-m", "file", local, &format!("{url}/canonical.conf")]);
+        assert_eq!(code, ExitCode::Success);
+        let args = diff_args(&[
+            "-m",
+            "file",
+            "--timeout",
+            "5s",
+            local,
+            &format!("{url}/gone"),
+        ]);
+        let err = run_diff(&mut Vec::new(), args).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(DiffError::FetchFailed { .. })),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn missing_substrings_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- out_contains: [ready, \"port 80\"]\n- out_contains: [ready, \"port 443\", done]\n";
+        let runner = runner(
+            dir.path(),
+            "echo 'server ready on port 80'",
+            yaml,
+            RunOptions::default(),
+        );
+        let mut cases = runner.test_cases.cases();
+        let (_, case) = cases.next().unwrap();
+        let (outcome, report) = runner.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed, "{report}");
+
+        let (_, case) = cases.next().unwrap();
+        let (outcome, report) = runner.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Failed);
+        let report = strip(&report);
+        assert!(report.contains("missing: \"port 443\"\n"), "{report}");
+        assert!(report.contains("missing: \"done\"\n"), "{report}");
+        assert!(!report.contains("missing: \"ready\""), "{report}");
+    }
+
+    #[test]
+    fn group_runs_only_its_own_cases() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "groups:\n  smoke:\n    tests:\n    - args: one\n      out: \"one\\n\"\n  full:\n    tests:\n    - args: two\n      out: \"two\\n\"\n    - args: three\n      out: wrong\n";
+        let options = RunOptions {
+            summary_only: true,
+            group: Some("smoke".to_owned()),
+            ..RunOptions::default()
+        };
+        let log = dir.path().join("ran");
+        let program = format!("echo \"$1\" >> '{}'; echo \"$1\"", log.display());
+        let runner = runner(dir.path(), &program, yaml, options);
+        let mut out = Vec::new();
+        assert_eq!(runner.run_to(&mut out).unwrap(), ExitCode::Success);
+        assert_eq!(std::fs::read_to_string(log).unwrap(), "one\n");
+        assert_eq!(
+            strip(&String::from_utf8(out).unwrap()),
+            "ran 1, skipped 0, filtered 2 (of 3)\nsmoke: 1 passed, 0 failed\n1 passed, 0 failed\n"
+        );
+    }
+
+    #[test]
+    fn porcelain_prints_one_line_per_case() {
+        assert_eq!(
+            porcelain_line(Outcome::Passed, Duration::from_millis(12), "adds\tnumbers"),
+            "PASS\t12\tadds numbers\n"
+        );
+        assert_eq!(
+            porcelain_line(Outcome::Failed, Duration::from_millis(3), "prints"),
+            "FAIL\t3\tprints\n"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- note: same\n  out: \"x\\n\"\n- note: other\n  out: \"y\\n\"\n";
+        let options = RunOptions {
+            porcelain: true,
+            ..RunOptions::default()
+        };
+        let runner = runner(dir.path(), "echo x", yaml, options);
+        let mut out = Vec::new();
+        assert_eq!(runner.run_to(&mut out).unwrap(), ExitCode::Differs);
+        let runtimes = Regex::new("\t[0-9]+\t").unwrap();
+        assert_eq!(
+            runtimes.replace_all(&String::from_utf8(out).unwrap(), "\tMS\t"),
+            "PASS\tMS\tsame\nFAIL\tMS\tother\n"
+        );
+    }
+
+    #[test]
+    fn program_may_leave_input_unread() {
+        let dir = tempfile::tempdir().unwrap();
+        // More input than a pipe buffers, so writing it fails once the
+        // program has exited
+        let input = "first\n".to_owned() + &"more\n".repeat(100_000);
+        let yaml = serde_yaml::to_string(&serde_json::json!({
+            "tests": [{"input": input, "out": "first\n"}],
+        }))
+        .unwrap();
+        let runner = runner(
+            dir.path(),
+            "read line; echo \"$line\"",
+            &yaml,
+            RunOptions::default(),
+        );
+        let (_, case) = runner.test_cases.cases().next().unwrap();
+        let (outcome, _) = runner.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed);
+    }
+
+    #[test]
+    fn suite_timeout_leaves_later_cases_unrun() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- note: one\n- note: two\n- note: three\n- note: four\n";
+        let options = RunOptions {
+            jobs: 1,
+            summary_only: true,
+            suite_timeout: Some(Duration::from_millis(750)),
+            ..RunOptions::default()
+        };
+        // The second case is stopped at the deadline and the others never start
+        let runner = runner(dir.path(), "sleep 0.5", yaml, options);
+        let mut out = Vec::new();
+        assert_eq!(runner.run_to(&mut out).unwrap(), ExitCode::Timeout);
+        let out = strip(&String::from_utf8(out).unwrap());
+        assert!(out.ends_with("1 passed, 0 failed, 3 not run\n"), "{out}");
+    }
+
+    #[test]
+    fn latin1_files_are_decoded_before_diffing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        // 0xE9 is é in Latin-1 and not valid UTF-8 on its own
+        std::fs::write(&left, b"caf\xe9\n").unwrap();
+        std::fs::write(&right, b"caf\xe9s\n").unwrap();
+        let (left, right) = (left.to_str().unwrap(), right.to_str().unwrap());
+        let args = [
+            "-m",
+            "file",
+            "-f",
+            "unified",
+            "--encoding",
+            "latin1",
+            left,
+            right,
+        ];
+        let (code, out) = run_cli(&args);
+        assert_eq!(code, ExitCode::Differs);
+        assert!(out.contains("-café\n+cafés\n"), "{out}");
+
+        let mut out = Vec::new();
+        assert!(run_diff(&mut out, diff_args(&["-m", "file", left, right])).is_err());
+    }
+
+    #[test]
+    fn baseline_flags_cases_that_changed_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let baseline = dir.path().join("baseline.json");
+        std::fs::write(
+            &baseline,
+            r#"{"cases": [
+                {"note": "one", "outcome": "passed", "duration_ms": 1},
+                {"note": "two", "outcome": "failed", "duration_ms": 1},
+                {"note": "three", "outcome": "passed", "duration_ms": 1}
+            ]}"#,
+        )
+        .unwrap();
+        let options = RunOptions {
+            summary_only: true,
+            baseline: Some(Report::load(&baseline).unwrap().outcomes()),
+            ..RunOptions::default()
+        };
+        let yaml =
+            "tests:\n- note: one\n  out: y\n- note: two\n  out: x\n- note: three\n  out: x\n";
+        let runner = runner(dir.path(), "printf x", yaml, options);
+        let mut out = Vec::new();
+        assert_eq!(runner.run_to(&mut out).unwrap(), ExitCode::Differs);
+        assert_eq!(
+            strip(&String::from_utf8(out).unwrap()),
+            "2 passed, 1 failed\nREGRESSION one\nFIXED two\n"
+        );
+    }
+
+    /// Checks that every segment of `diff` is found at its offsets in the
+    /// side or sides it belongs to.
+    fn assert_indexed(diff: &[Diff<char>], left: &str, right: &str) {
+        let (left, right) = (
+            left.chars().collect::<Vec<_>>(),
+            right.chars().collect::<Vec<_>>(),
+        );
+        for (segment, (left_start, right_start, op)) in diff.iter().zip(index_diffs(diff)) {
+            assert_eq!(op, segment.op());
+            let len = segment.size();
+            if op != Ops::Insert {
+                assert_eq!(&left[left_start..left_start + len], segment.data());
+            }
+            if op != Ops::Delete {
+                assert_eq!(&right[right_start..right_start + len], segment.data());
+            }
+        }
+    }
+
+    #[test]
+    fn index_gives_the_offsets_of_each_segment() {
+        let chars = |text: &str| text.chars().collect::<Vec<_>>();
+        let diff = [
+            Diff::equal(&chars("The ")),
+            Diff::delete(&chars("cat")),
+            Diff::insert(&chars("dog")),
+            Diff::equal(&chars(" sat")),
+            Diff::insert(&chars(" down")),
+        ];
+        assert_eq!(
+            index_diffs(&diff),
+            [
+                (0, 0, Ops::Equal),
+                (4, 4, Ops::Delete),
+                (7, 4, Ops::Insert),
+                (7, 7, Ops::Equal),
+                (11, 11, Ops::Insert),
+            ]
+        );
+
+        let (left, right) = ("The cat sat on the mat", "The cart sits on a mat");
+        let dmp = DiffMatchPatch::new();
+        let mut raw = dmp.diff_main::<Efficient>(left, right).unwrap();
+        let to_chars = |diffs: &[Diff<u8>]| {
+            diffs
+                .iter()
+                .map(|diff| Diff::new(diff.op(), &chars(std::str::from_utf8(diff.data()).unwrap())))
+                .collect::<Vec<_>>()
+        };
+        let uncleaned = to_chars(&raw);
+        assert_indexed(&uncleaned, left, right);
+        DiffMatchPatch::diff_cleanup_semantic(&mut raw);
+        let cleaned = to_chars(&raw);
+        assert_ne!(cleaned.len(), uncleaned.len(), "cleanup moves boundaries");
+        assert_indexed(&cleaned, left, right);
+    }
+
+    #[test]
+    fn cases_are_listed_in_the_sort_order() {
+        let notes = ["quick", "slow", "medium", "also quick"].map(|note| TestCase {
+            note: Some(note.to_owned()),
+            ..TestCase::default()
+        });
+        let cases = notes.iter().enumerate().collect::<Vec<_>>();
+        let outcomes = [
+            (10, Outcome::Passed),
+            (300, Outcome::Passed),
+            (50, Outcome::Failed),
+            (10, Outcome::NotRun),
+        ]
+        .map(|(millis, status)| CaseOutcome {
+            status,
+            duration: Duration::from_millis(millis),
+            actual_output: None,
+        });
+        assert_eq!(SortOrder::Source.order(&cases, &outcomes), [0, 1, 2, 3]);
+        assert_eq!(SortOrder::Time.order(&cases, &outcomes), [1, 2, 0, 3]);
+        assert_eq!(SortOrder::Name.order(&cases, &outcomes), [3, 2, 0, 1]);
+        assert_eq!(SortOrder::Status.order(&cases, &outcomes), [2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn events_stream_a_start_and_a_finish_per_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- note: one\n  out: \"x\\n\"\n- note: two\n  out: y\n";
+        let options = RunOptions {
+            jobs: 1,
+            events: true,
+            ..RunOptions::default()
+        };
+        let runner = runner(dir.path(), "echo x", yaml, options);
+        let mut out = Vec::new();
+        assert_eq!(runner.run_to(&mut out).unwrap(), ExitCode::Differs);
+        let events = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let sequence = events
+            .iter()
+            .map(|event| {
+                assert!(event["event"] == "start" || event["ms"].is_u64());
+                (
+                    event["event"].as_str().unwrap(),
+                    event["note"].as_str().unwrap(),
+                    event["status"].as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sequence,
+            [
+                ("start", "one", None),
+                ("finish", "one", Some("passed")),
+                ("start", "two", None),
+                ("finish", "two", Some("failed")),
+            ]
+        );
+    }
+
+    #[test]
+    fn merged_stderr_is_compared_with_stdout() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = "echo one; echo two >&2; echo three";
+        let yaml = "tests:\n- out: \"one\\ntwo\\nthree\\n\"\n  merge_stderr: true\n- out: \"one\\nthree\\n\"\n";
+        let runner = runner(dir.path(), program, yaml, RunOptions::default());
+        for (_, case) in runner.test_cases.cases() {
+            let (outcome, _) = runner.run_test_case(case, None).unwrap();
+            assert_eq!(outcome.status, Outcome::Passed);
+        }
+    }
+
+    #[test]
+    fn accepting_regressions_updates_only_those() {
+        let dir = tempfile::tempdir().unwrap();
+        let baseline = dir.path().join("baseline.json");
+        std::fs::write(
+            &baseline,
+            r#"{"cases": [
+                {"note": "was passing", "outcome": "passed", "duration_ms": 1},
+                {"note": "was failing", "outcome": "failed", "duration_ms": 1}
+            ]}"#,
+        )
+        .unwrap();
+        let options = RunOptions {
+            summary_only: true,
+            baseline: Some(Report::load(&baseline).unwrap().outcomes()),
+            update: Some(Update::All),
+            accept_regressions: true,
+            ..RunOptions::default()
+        };
+        let yaml = "tests:\n- note: was passing\n  out: old\n- note: was failing\n  out: old\n";
+        let runner = runner(dir.path(), "printf new", yaml, options);
+        runner.run_to(std::io::sink()).unwrap();
+        let suite = TestSuite::load(dir.path().join("tests.yaml")).unwrap();
+        let outs = suite
+            .cases()
+            .map(|(_, case)| case.out.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(outs, ["new", "old"]);
+    }
+
+    #[test]
+    fn transient_spawn_errors_are_retried() {
+        let would_block = || std::io::Error::from(std::io::ErrorKind::WouldBlock);
+        let mut attempts = 0;
+        let mut command = Command::new("true");
+        let child = spawn_with_retries(&mut command, 3, |command| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(would_block())
+            } else {
+                command.spawn()
+            }
+        });
+        assert!(child.unwrap().wait().unwrap().success());
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let err = spawn_with_retries(&mut command, 2, |_| {
+            attempts += 1;
+            Err(would_block())
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        assert_eq!(attempts, 3, "the first try and two retries");
+
+        let mut attempts = 0;
+        let err = spawn_with_retries(&mut command, 2, |_| {
+            attempts += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(attempts, 1, "a missing program is not retried");
+    }
+
+    #[test]
+    fn notes_are_grouped_by_outcome() {
+        let results = [
+            ("one", Outcome::Passed),
+            ("two", Outcome::Failed),
+            ("three", Outcome::NotRun),
+            ("four", Outcome::UnexpectedPass),
+            ("five", Outcome::ExpectedFailure),
+        ];
+        assert_eq!(
+            strip(&grouped_notes(results.into_iter(), &["six"])),
+            "FAILURES\n  two\n  four\nTIMEOUTS\n  three\nSKIPPED\n  six\n"
+        );
+        let passed = [("one", Outcome::Passed)];
+        assert_eq!(grouped_notes(passed.into_iter(), &[]), "");
+    }
+
+    #[test]
+    fn base64_inputs_are_decoded_before_diffing() {
+        let (left, right) = ("aGVsbG8gd29ybGQ=", "aGVsbG8gd09ybGQ=");
+        let (code, out) = run_cli(&["-m", "interactive", "--decode", "base64", left, right]);
+        assert_eq!(code, ExitCode::Differs);
+        assert_eq!(out, "hello woOrld\n");
+
+        let (_, dump) = run_cli(&[
+            "-m",
+            "interactive",
+            "--decode",
+            "base64",
+            "--binary",
+            left,
+            right,
+        ]);
+        assert!(
+            dump.starts_with("00000000: 68 65 6c 6c 6f 20 77 64f 72 6c 64 "),
+            "{dump}"
+        );
+
+        let mut out = Vec::new();
+        let args = diff_args(&[
+            "-m",
+            "interactive",
+            "--decode",
+            "base64",
+            "not base64!",
+            right,
+        ]);
+        let err = run_diff(&mut out, args).unwrap_err();
+        assert_eq!(err.to_string(), "Left input is not valid base64");
+    }
+
+    #[test]
+    fn failure_hook_gets_the_note_and_the_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let (notes, reports) = (dir.path().join("notes"), dir.path().join("reports"));
+        let hook = script(
+            dir.path(),
+            "hook",
+            &format!(
+                "echo \"$DIFF_CASE_NOTE\" >> '{}'; cat >> '{}'",
+                notes.display(),
+                reports.display()
+            ),
+        );
+        let options = RunOptions {
+            on_failure: Some(hook.display().to_string()),
+            ..RunOptions::default()
+        };
+        let yaml = "tests:\n- note: passes\n  out: \"x\\n\"\n- note: fails\n  out: \"y\\n\"\n";
+        let runner = runner(dir.path(), "echo x", yaml, options);
+        assert_eq!(runner.run_to(std::io::sink()).unwrap(), ExitCode::Differs);
+        assert_eq!(std::fs::read_to_string(notes).unwrap(), "fails\n");
+        let report = strip(&std::fs::read_to_string(reports).unwrap());
+        assert!(report.contains("fails"), "{report}");
+    }
+
+    #[test]
+    fn stable_ends_match_around_a_changing_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = "echo 'tool v1.0'; echo \"pid $$\"; echo done";
+        let yaml = "tests:\n- out_prefix: \"tool v1.0\\n\"\n  out_suffix: \"done\\n\"\n- out_prefix: tool v2\n";
+        let runner = runner(dir.path(), program, yaml, RunOptions::default());
+        let mut cases = runner.test_cases.cases();
+        let (_, case) = cases.next().unwrap();
+        // Each run prints another pid between the stable ends
+        for _ in 0..2 {
+            let (outcome, report) = runner.run_test_case(case, None).unwrap();
+            assert_eq!(outcome.status, Outcome::Passed, "{report}");
+        }
+
+        let (_, case) = cases.next().unwrap();
+        let (outcome, report) = runner.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Failed);
+        let report = strip(&report);
+        assert!(
+            report.contains("prefix differs at char 7: expected \"2\", got \"1.0\\npid "),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn fold_shortens_only_long_unchanged_runs() {
+        let long = "x".repeat(40);
+        let (left, right) = (format!("{long}cat sat"), format!("{long}cot sat"));
+        let (code, out) = run_cli(&["-m", "interactive", "--fold", "10", &left, &right]);
+        assert_eq!(code, ExitCode::Differs);
+        assert_eq!(out, "…[41 chars]…aot sat\n");
+        let (_, unfolded) = run_cli(&["-m", "interactive", &left, &right]);
+        assert_eq!(unfolded, format!("{long}caot sat\n"));
+    }
+
+    #[test]
+    fn snapshot_fails_on_change_until_updated() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshots = dir.path().join("snapshots");
+        let snapshots = snapshots.to_str().unwrap();
+        let snapshot = |text: &str, update: bool| {
+            let mut args = vec![
+                "-m",
+                "interactive",
+                "--snapshot-dir",
+                snapshots,
+                "--name",
+                "greeting",
+                text,
+            ];
+            if update {
+                args.push("--update-snapshots");
+            }
+            run_cli(&args)
+        };
+        let stored = snapshot("hello", false);
+        assert_eq!(stored.0, ExitCode::Success);
+        assert!(stored.1.starts_with("Stored snapshot "), "{}", stored.1);
+        assert_eq!(snapshot("hello", false).0, ExitCode::Success);
+        assert_eq!(snapshot("hello world", false).0, ExitCode::Differs);
+
+        assert_eq!(snapshot("hello world", true).0, ExitCode::Success);
+        let path = Path::new(snapshots).join("greeting.snap");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "hello world");
+        assert_eq!(snapshot("hello world", false).0, ExitCode::Success);
+    }
+
+    #[test]
+    fn equal_only_prints_just_the_unchanged_text() {
+        let (code, out) = run_cli(&[
+            "-m",
+            "interactive",
+            "--equal-only",
+            "the red fox",
+            "the fox ran",
+        ]);
+        assert_eq!(code, ExitCode::Differs);
+        assert_eq!(out, "the fox\n");
+        let diff = compute_diff("the red fox", "the fox ran")
+            .unwrap()
+            .equal_only();
+        assert!(diff.0.iter().all(|segment| segment.op() == Ops::Equal));
+    }
+
+    #[test]
+    fn equal_via_command_decides_the_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- out: \"expected\\n\"\n";
+        for (comparator, status) in [("true", Outcome::Passed), ("false", Outcome::Failed)] {
+            let options = RunOptions {
+                equal_via: Some(comparator.to_owned()),
+                ..RunOptions::default()
+            };
+            let runner = runner(dir.path(), "echo actual", yaml, options);
+            let (_, case) = runner.test_cases.cases().next().unwrap();
+            let (outcome, report) = runner.run_test_case(case, None).unwrap();
+            assert_eq!(outcome.status, status, "{comparator}");
+            if status == Outcome::Failed {
+                // The textual diff still shows what differs
+                assert!(strip(&report).contains("\nexpeactedual\n"), "{report}");
+            }
+        }
+    }
+
+    #[test]
+    fn inputs_are_fed_in_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = "read first; echo \"1: $first\"; read second; echo \"2: $second\"";
+        let yaml = "tests:\n- inputs: [hello, \"world\\n\"]\n  out: \"1: hello\\n2: world\\n\"\n";
+        let runner = runner(dir.path(), program, yaml, RunOptions::default());
+        let (_, case) = runner.test_cases.cases().next().unwrap();
+        assert_eq!(
+            runner.with_generated_input(case).unwrap().stdin(),
+            ["hello\n", "world\n"]
+        );
+        let (outcome, report) = runner.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed, "{report}");
+
+        let both = TestCase {
+            input: Some("x".to_owned()),
+            ..case.clone()
+        };
+        assert!(runner.generate_input(&both).is_err());
+    }
+
+    #[test]
+    fn diff_timeout_bounds_the_diff_time() {
+        let mut state = 7u32;
+        let mut text = || {
+            (0..30_000)
+                .map(|_| {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    char::from(b"abcd"[(state >> 16) as usize % 4])
+                })
+                .collect::<String>()
+        };
+        let (left, right) = (text(), text());
+        let start = Instant::now();
+        let diff = Engine::Compat
+            .diff(&left, &right, Duration::from_millis(100))
+            .unwrap();
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "{:?}",
+            start.elapsed()
+        );
+        let side = |op| {
+            diff.0
+                .iter()
+                .filter(|segment| segment.op() != op)
+                .flat_map(Diff::data)
+                .collect::<String>()
+        };
+        assert_eq!((side(Ops::Insert), side(Ops::Delete)), (left, right));
+    }
+
+    #[test]
+    fn allowed_nonzero_exit_passes_on_matching_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- out: \"done\\n\"\n  allow_nonzero_exit: true\n- out: \"done\\n\"\n";
+        let options = RunOptions {
+            require_success: true,
+            ..RunOptions::default()
+        };
+        let runner = runner(dir.path(), "echo done; exit 1", yaml, options);
+        let statuses = runner
+            .test_cases
+            .cases()
+            .map(|(_, case)| runner.run_test_case(case, None).unwrap().0.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, [Outcome::Passed, Outcome::Failed]);
+    }
+
+    #[test]
+    fn json_is_compact_or_pretty_as_asked() {
+        let args = ["-m", "interactive", "-f", "json", "ab", "ac"];
+        let json = |flag| {
+            let (code, out) = run_cli(&[&args[..], &[flag]].concat());
+            assert_eq!(code, ExitCode::Differs);
+            let value = serde_json::from_str::<serde_json::Value>(&out).unwrap();
+            (out, value)
+        };
+        let (compact, compact_value) = json("--json-compact");
+        assert!(!compact.trim_end().contains('\n'), "{compact}");
+        let (pretty, pretty_value) = json("--json-pretty");
+        assert!(pretty.trim_end().contains("\n  "), "{pretty}");
+        assert_eq!(compact_value, pretty_value);
+        assert_eq!(to_json(&[1, 2], false).unwrap(), "[1,2]");
+        assert_eq!(to_json(&[1, 2], true).unwrap(), "[\n  1,\n  2\n]");
+    }
+
+    /// Runs git in `dir` with a fixed identity, returning whether it
+    /// succeeded, or `None` if git is not installed.
+    fn git(dir: &Path, args: &[&str]) -> Option<bool> {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .ok()?;
+        Some(status.success())
+    }
+
+    #[test]
+    fn git_revision_is_diffed_against_the_working_file() {
+        let dir = tempfile::tempdir().unwrap();
+        if git(dir.path(), &["init", "-q"]).is_none() {
+            return;
+        }
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, "one\ntwo\n").unwrap();
+        assert_eq!(git(dir.path(), &["add", "notes.txt"]), Some(true));
+        assert_eq!(git(dir.path(), &["commit", "-qm", "notes"]), Some(true));
+        let path = file.to_str().unwrap();
+        let args = ["-m", "file", "-f", "unified", "--git", "HEAD", path];
+        assert_eq!(run_cli(&args).0, ExitCode::Success);
+
+        std::fs::write(&file, "one\n2\n").unwrap();
+        let (code, out) = run_cli(&args);
+        assert_eq!(code, ExitCode::Differs);
+        assert!(out.ends_with("@@ -1,2 +1,2 @@\n one\n-two\n+2\n"), "{out}");
+
+        let outside = tempfile::tempdir().unwrap();
+        let loose = outside.path().join("loose.txt");
+        std::fs::write(&loose, "x\n").unwrap();
+        let args = diff_args(&["-m", "file", "--git", "HEAD", loose.to_str().unwrap()]);
+        let err = run_diff(&mut Vec::new(), args).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read "), "{err}");
+    }
+
+    #[test]
+    fn repro_quotes_inputs_with_spaces() {
+        assert_eq!(shell_quote("plain-word.txt"), "plain-word.txt");
+        assert_eq!(shell_quote("two words"), "'two words'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- input: \"it's two words\"\n  args: -n\n  out: nothing\n";
+        let runner = runner(dir.path(), "cat; echo \" $1\"", yaml, RunOptions::default());
+        let (_, case) = runner.test_cases.cases().next().unwrap();
+        let (outcome, report) = runner.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Failed);
+        let program = runner.program_path.display();
+        let repro = format!("printf '%s' 'it'\\''s two words' | {program} -n");
+        assert!(
+            strip(&report).contains(&format!("repro: {repro}\n")),
+            "{report}"
+        );
+        let output = Command::new("sh").args(["-c", &repro]).output().unwrap();
+        assert_eq!(output.stdout, b"it's two words -n\n");
+    }
+
+    #[test]
+    fn max_segments_renders_only_the_first_ones() {
+        let (left, right) = ("a1b1c1d1e1f1", "a2b2c2d2e2f2");
+        let full = compute_diff(left, right).unwrap();
+        assert!(full.0.len() > 10, "the diff is fragmented");
+        let args = [
+            "-m",
+            "interactive",
+            "-f",
+            "json",
+            "--max-segments",
+            "4",
+            left,
+            right,
+        ];
+        let (code, out) = run_cli(&args);
+        assert_eq!(code, ExitCode::Differs);
+        let json = serde_json::from_str::<serde_json::Value>(&out).unwrap();
+        assert_eq!(json, DiffVec(full.0[..4].to_vec()).to_json());
+
+        let args = ["-m", "interactive", "--max-segments", "100", left, right];
+        assert_eq!(run_cli(&args).1, "a12b12c12d12e12f12\n", "under the limit");
+    }
+
+    /// A runner of the shell script `program` over a fixtures directory in
+    /// `dir` with the given files.
+    fn fixtures_runner(
+        dir: &Path,
+        program: &str,
+        files: &[(&str, &str)],
+        options: RunOptions,
+    ) -> TestRunner {
+        let program = script(dir, "program", program);
+        let fixtures = dir.join("fixtures");
+        tree(&fixtures, files);
+        TestRunner::new(
+            program.to_str().unwrap(),
+            fixtures.to_str().unwrap(),
+            options,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn fixture_dirs_are_cases() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = RunOptions {
+            summary_only: true,
+            ..RunOptions::default()
+        };
+        let files = [
+            ("greets/args", "hello\n"),
+            ("greets/expected", "hello stdin\n"),
+            ("greets/input", "stdin"),
+            ("fails/args", "bye"),
+            ("fails/expected", "hello\n"),
+        ];
+        let runner = fixtures_runner(dir.path(), "echo \"$1\" \"$(cat)\"", &files, options);
+        let notes = runner
+            .test_cases
+            .cases()
+            .map(|(_, case)| case.note())
+            .collect::<Vec<_>>();
+        assert_eq!(notes, ["fails", "greets"]);
+        let mut out = Vec::new();
+        assert_eq!(runner.run_to(&mut out).unwrap(), ExitCode::Differs);
+        assert_eq!(
+            strip(&String::from_utf8(out).unwrap()),
+            "1 passed, 1 failed\n"
+        );
+    }
+
+    #[test]
+    fn collate_merges_adjacent_segments_with_the_same_op() {
+        let chars = |text: &str| text.chars().collect::<Vec<_>>();
+        let diffs = vec![
+            Diff::equal(&chars("a")),
+            Diff::delete(&chars("b")),
+            Diff::delete(&chars("c")),
+            Diff::insert(&chars("d")),
+            Diff::delete(&chars("e")),
+        ];
+        let collated = collate(diffs);
+        let segments = collated
+            .iter()
+            .map(|diff| (diff.op(), diff.data().iter().collect::<String>()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            segments,
+            [
+                (Ops::Equal, "a".to_owned()),
+                (Ops::Delete, "bc".to_owned()),
+                (Ops::Insert, "d".to_owned()),
+                (Ops::Delete, "e".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn since_runs_only_fixtures_changed_since_the_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        if git(dir.path(), &["init", "-q"]).is_none() {
+            return;
+        }
+        let fixtures = dir.path().join("fixtures");
+        tree(
+            &fixtures,
+            &[("one/input", "1"), ("two/input", "2"), ("three/input", "3")],
+        );
+        assert_eq!(git(dir.path(), &["add", "fixtures"]), Some(true));
+        assert_eq!(git(dir.path(), &["commit", "-qm", "fixtures"]), Some(true));
+        std::fs::write(fixtures.join("two/input"), "22").unwrap();
+
+        let log = dir.path().join("ran");
+        let program = format!("cat >> '{}'", log.display());
+        let run = |since: &str| {
+            let options = RunOptions {
+                since: Some(since.to_owned()),
+                summary_only: true,
+                jobs: 1,
+                ..RunOptions::default()
+            };
+            let runner = fixtures_runner(dir.path(), &program, &[], options);
+            runner.run_to(std::io::sink()).unwrap();
+            std::fs::read_to_string(&log).unwrap_or_default()
+        };
+        assert_eq!(run("HEAD"), "22");
+
+        std::fs::remove_file(&log).unwrap();
+        assert_eq!(
+            run("no-such-revision"),
+            "1322",
+            "all run when git cannot tell"
+        );
+    }
+
+    #[test]
+    fn dots_mark_each_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- out: \"x\\n\"\n- out: y\n- out: y\n  should_fail: true\n";
+        let plain = runner(dir.path(), "echo x", yaml, RunOptions::default());
+        let mut statuses = plain
+            .test_cases
+            .cases()
+            .map(|(_, case)| plain.run_test_case(case, None).unwrap().0.status)
+            .collect::<Vec<_>>();
+        statuses.extend([Outcome::NotRun, Outcome::Skipped, Outcome::UnexpectedPass]);
+        let dots = statuses.into_iter().map(dot).collect::<String>();
+        assert_eq!(strip(&dots), ".F.TSF");
+
+        // The run itself then lists only the failure, after the dots
+        let options = RunOptions {
+            dots: true,
+            ..RunOptions::default()
+        };
+        let dotted = runner(dir.path(), "echo x", yaml, options);
+        let mut out = Vec::new();
+        assert_eq!(dotted.run_to(&mut out).unwrap(), ExitCode::Differs);
+        let out = strip(&String::from_utf8(out).unwrap());
+        assert!(out.starts_with("Unnamed test case\n"), "{out}");
+        assert_eq!(out.matches("Unnamed test case").count(), 1, "{out}");
+    }
+
+    #[test]
+    fn case_may_ignore_case_while_the_run_does_not() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- out: \"HELLO\\n\"\n  ignore_case: true\n- out: \"HELLO\\n\"\n";
+        let plain = runner(dir.path(), "echo hello", yaml, RunOptions::default());
+        let statuses = plain
+            .test_cases
+            .cases()
+            .map(|(_, case)| plain.run_test_case(case, None).unwrap().0.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, [Outcome::Passed, Outcome::Failed]);
+
+        let options = RunOptions {
+            normalization: Normalization {
+                ignore_case: true,
+                ..Normalization::default()
+            },
+            ..RunOptions::default()
+        };
+        let ignoring = runner(
+            dir.path(),
+            "echo hello",
+            "tests:\n- out: HELLO\n  ignore_case: false\n",
+            options,
+        );
+        let (_, case) = ignoring.test_cases.cases().next().unwrap();
+        assert!(
+            !ignoring.case_options(case).normalization.ignore_case,
+            "the case wins"
+        );
+    }
+
+    #[test]
+    fn short_equal_run_between_deletes_is_absorbed() {
+        let chars = |text: &str| text.chars().collect::<Vec<_>>();
+        let diff = DiffVec(vec![
+            Diff::equal(&chars("start ")),
+            Diff::delete(&chars("ab")),
+            Diff::equal(&chars("c")),
+            Diff::delete(&chars("de")),
+            Diff::equal(&chars(" end")),
+        ]);
+        let absorbed = diff.absorbed(3);
+        assert_eq!(segments(&absorbed, Ops::Delete), ["abcde"]);
+        assert_eq!(segments(&absorbed, Ops::Insert), ["c"]);
+        assert_eq!(
+            segments(&absorbed, Ops::Equal),
+            ["start ", " end"],
+            "longer runs stay"
+        );
+        assert_eq!(
+            diff.absorbed(1).0.len(),
+            diff.0.len(),
+            "c is not shorter than 1"
+        );
+    }
+
+    #[test]
+    fn differential_skips_the_cases_marked_skip() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = "tests:\n- args: one\n- args: two\n  skip: true\n";
+        let reference = runner(dir.path(), "echo \"$1\"", yaml, RunOptions::cli_defaults());
+        let candidate = script(dir.path(), "candidate", "echo one");
+        let mut out = Vec::new();
+        let code = reference
+            .run_differential(&mut out, candidate.to_str().unwrap())
+            .unwrap();
+        assert_eq!(code, ExitCode::Success);
+        let out = strip(&String::from_utf8(out).unwrap());
+        assert!(out.ends_with("\n1 passed, 0 failed\n"), "{out}");
+    }
+
+    #[test]
+    fn null_data_marks_only_the_changed_record() {
+        let dir = tempfile::tempdir().unwrap();
+        tree(dir.path(), &[("left", "a\0b\0c"), ("right", "a\0x\0c")]);
+        let (left, right) = (dir.path().join("left"), dir.path().join("right"));
+        let (left, right) = (left.to_str().unwrap(), right.to_str().unwrap());
+        let (code, out) = run_cli(&[
+            "-m",
+            "file",
+            "-f",
+            "unified",
+            "--no-header",
+            "--null-data",
+            left,
+            right,
+        ]);
+        assert_eq!(code, ExitCode::Differs);
+        assert!(!out.contains('\n'), "{out:?}");
+        assert_eq!(
+            out,
+            "@@ -1,3 +1,3 @@\0 a\0-b\0+x\0 c\0\\ No newline at end of file\0"
+        );
+    }
+
+    #[test]
+    fn equal_via_command_is_tried_on_every_acceptable_output() {
+        let dir = tempfile::tempdir().unwrap();
+        // Accepts only an expected output of `anything`, however far it is
+        let comparator = script(
+            dir.path(),
+            "comparator",
+            "tr '\\0' '\\n' | head -n 1 | grep -qx anything",
+        );
+        let options = || RunOptions {
+            equal_via: Some(comparator.to_str().unwrap().to_owned()),
+            ..RunOptions::default()
+        };
+        let yaml = "tests:\n- out_any: [\"hellx\\n\", anything]\n";
+        let accepting = runner(dir.path(), "echo hello", yaml, options());
+        let (_, case) = accepting.test_cases.cases().next().unwrap();
+        let (outcome, report) = accepting.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed, "{report}");
+
+        let yaml = "tests:\n- out_any: [\"hellx\\n\", \"h\\n\"]\n";
+        let rejecting = runner(dir.path(), "echo hello", yaml, options());
+        let (_, case) = rejecting.test_cases.cases().next().unwrap();
+        let (outcome, report) = rejecting.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Failed);
+        // The diff is against the closest candidate
+        assert!(strip(&report).contains("\nhellxo\n"), "{report}");
+    }
+
+    #[test]
+    fn each_input_waits_for_the_reply_to_the_one_before() {
+        let dir = tempfile::tempdir().unwrap();
+        // Prints `early` if the second input is there before its prompt
+        let program = "read first\nbash -c 'read -t 0' && echo early\necho second?\nread second\necho \"$first $second\"";
+        let yaml = "tests:\n- inputs: [one, two]\n  out: \"second?\\none two\\n\"\n";
+        let repl = runner(dir.path(), program, yaml, RunOptions::default());
+        let (_, case) = repl.test_cases.cases().next().unwrap();
+        let (outcome, report) = repl.run_test_case(case, None).unwrap();
+        assert_eq!(outcome.status, Outcome::Passed, "{report}");
+    }
+
+    #[test]
+    fn update_rejects_a_fixtures_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        tree(dir.path(), &[("fixtures/one/input", "x")]);
+        let program = script(dir.path(), "program", "cat");
+        let fixtures = dir.path().join("fixtures");
+        let (program, fixtures) = (program.to_str().unwrap(), fixtures.to_str().unwrap());
+        for flag in ["--update", "--interactive"] {
+            let args = diff_args(&["-m", "program", flag, program, fixtures]);
+            let err = run_diff(&mut std::io::sink(), args).unwrap_err();
+            assert!(
+                err.to_string().starts_with("--update only applies"),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn the_last_of_collate_and_no_collate_wins() {
+        for (args, collate) in [
+            (&[][..], true),
+            (&["--no-collate"][..], false),
+            (&["--no-collate", "--collate"][..], true),
+            (&["--collate", "--no-collate"][..], false),
+        ] {
+            let options = diff_args(&[args, &["a", "b"]].concat()).diff_options();
+            assert_eq!(options.collate, collate, "{args:?}");
         }
     }
-    Ok(())
 }
@@ -0,0 +1,275 @@
+//! Myers' O(ND) diff algorithm over lines, as used by classic `diff`.
+
+use crate::DiffVec;
+use diff_match_patch_rs::{Ops, dmp::Diff};
+
+/// The furthest x reached on each diagonal `k = x - y`, for diagonals as
+/// far out as `-max..=max`.
+struct Frontier {
+    x: Vec<usize>,
+    max: usize,
+}
+
+impl Frontier {
+    fn new(max: usize) -> Self {
+        Self {
+            x: vec![0; 2 * max + 1],
+            max,
+        }
+    }
+
+    fn index(&self, k: isize) -> usize {
+        self.max
+            .checked_add_signed(k)
+            .expect("diagonals stay within the frontier")
+    }
+
+    fn get(&self, k: isize) -> usize {
+        self.x[self.index(k)]
+    }
+
+    fn set(&mut self, k: isize, x: usize) {
+        let index = self.index(k);
+        self.x[index] = x;
+    }
+
+    /// The x from which the next edit onto diagonal `k` starts, after `d`
+    /// edits: down from `k + 1` (an insertion) or right from `k - 1` (a
+    /// deletion), whichever got further.
+    fn start(&self, k: isize, d: isize) -> usize {
+        if k == -d || (k != d && self.get(k - 1) < self.get(k + 1)) {
+            self.get(k + 1)
+        } else {
+            self.get(k - 1) + 1
+        }
+    }
+}
+
+fn signed(len: usize) -> isize {
+    isize::try_from(len).expect("slice lengths fit in isize")
+}
+
+/// The y on diagonal `k` at `x`.
+fn y_at(x: usize, k: isize) -> usize {
+    x.checked_add_signed(-k)
+        .expect("the diagonal passes through the edit graph")
+}
+
+/// A point on the shortest path from the start to the end of the edit
+/// graph of `left` and `right` around its middle, found by extending paths
+/// from both ends until they overlap.
+///
+/// Only the furthest points of the latest paths are kept, so this takes
+/// O(N + M) memory rather than the O(D * (N + M)) of recording every step.
+fn middle_snake<T: PartialEq>(
+    left: &[T],
+    right: &[T],
+    forward: &mut Frontier,
+    backward: &mut Frontier,
+) -> (usize, usize) {
+    let (n, m) = (left.len(), right.len());
+    // The end point lies on diagonal `delta`
+    let delta = signed(n) - signed(m);
+    let odd = delta % 2 != 0;
+    forward.set(1, 0);
+    backward.set(1, 0);
+    for d in 0..=signed((n + m).div_ceil(2)) {
+        for k in (-d..=d).rev().step_by(2) {
+            let start = forward.start(k, d);
+            let mut x = start;
+            while x < n && y_at(x, k) < m && left[x] == right[y_at(x, k)] {
+                x += 1;
+            }
+            forward.set(k, x);
+            // Backward paths are on mirrored diagonals, counting from the end
+            if odd && (k - delta).abs() < d && x + backward.get(delta - k) >= n {
+                return (start, y_at(start, k));
+            }
+        }
+        // Going the other way round puts deletions before insertions
+        for k in (-d..=d).step_by(2) {
+            let mut x = backward.start(k, d);
+            while x < n && y_at(x, k) < m && left[n - x - 1] == right[m - y_at(x, k) - 1] {
+                x += 1;
+            }
+            backward.set(k, x);
+            if !odd && (k - delta).abs() <= d && x + forward.get(delta - k) >= n {
+                return (n - x, m - y_at(x, k));
+            }
+        }
+    }
+    unreachable!("paths from both ends overlap within (N + M) / 2 edits")
+}
+
+/// Appends the shortest sequence of operations turning `left` into `right`
+/// to `script`, splitting the inputs at the middle of the path until one of
+/// them is empty.
+fn conquer<T: PartialEq>(
+    left: &[T],
+    right: &[T],
+    forward: &mut Frontier,
+    backward: &mut Frontier,
+    script: &mut Vec<Ops>,
+) {
+    let prefix = left.iter().zip(right).take_while(|(l, r)| l == r).count();
+    script.extend(std::iter::repeat_n(Ops::Equal, prefix));
+    let (left, right) = (&left[prefix..], &right[prefix..]);
+    let suffix = left
+        .iter()
+        .rev()
+        .zip(right.iter().rev())
+        .take_while(|(l, r)| l == r)
+        .count();
+    let (left, right) = (&left[..left.len() - suffix], &right[..right.len() - suffix]);
+
+    if left.is_empty() {
+        script.extend(std::iter::repeat_n(Ops::Insert, right.len()));
+    } else if right.is_empty() {
+        script.extend(std::iter::repeat_n(Ops::Delete, left.len()));
+    } else {
+        let (x, y) = middle_snake(left, right, forward, backward);
+        conquer(&left[..x], &right[..y], forward, backward, script);
+        conquer(&left[x..], &right[y..], forward, backward, script);
+    }
+    script.extend(std::iter::repeat_n(Ops::Equal, suffix));
+}
+
+/// The shortest sequence of operations turning `left` into `right`.
+fn edit_script<T: PartialEq>(left: &[T], right: &[T]) -> Vec<Ops> {
+    let max = (left.len() + right.len()).div_ceil(2) + 1;
+    let (mut forward, mut backward) = (Frontier::new(max), Frontier::new(max));
+    let mut script = Vec::with_capacity(left.len() + right.len());
+    conquer(left, right, &mut forward, &mut backward, &mut script);
+    script
+}
+
+/// Computes a diff in which every segment consists of whole lines, like
+/// `compute_line_diff` but with the shortest edit script.
+pub fn line_diff(left: &str, right: &str) -> DiffVec {
+    let left = left.split_inclusive('\n').collect::<Vec<_>>();
+    let right = right.split_inclusive('\n').collect::<Vec<_>>();
+    let (mut left_lines, mut right_lines) = (left.iter(), right.iter());
+
+    let mut segments: Vec<(Ops, String)> = Vec::new();
+    for op in edit_script(&left, &right) {
+        let line = match op {
+            Ops::Equal => {
+                right_lines.next();
+                left_lines.next()
+            }
+            Ops::Delete => left_lines.next(),
+            Ops::Insert => right_lines.next(),
+        }
+        .expect("the edit script covers every line");
+        match segments.last_mut() {
+            Some((last, text)) if *last == op => text.push_str(line),
+            _ => segments.push((op, (*line).to_owned())),
+        }
+    }
+    DiffVec(
+        segments
+            .into_iter()
+            .map(|(op, text)| Diff::new(op, &text.chars().collect::<Vec<_>>()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_DIFF_TIMEOUT, compute_line_diff};
+
+    /// The length of the longest common subsequence, by dynamic programming.
+    fn lcs_len(left: &[u8], right: &[u8]) -> usize {
+        let mut row = vec![0; right.len() + 1];
+        for l in left {
+            let mut diagonal = 0;
+            for (j, r) in right.iter().enumerate() {
+                let above = row[j + 1];
+                row[j + 1] = if l == r {
+                    diagonal + 1
+                } else {
+                    above.max(row[j])
+                };
+                diagonal = above;
+            }
+        }
+        row[right.len()]
+    }
+
+    /// Checks that `script` turns `left` into `right` in the fewest edits.
+    fn assert_shortest(left: &[u8], right: &[u8]) {
+        let script = edit_script(left, right);
+        let (mut x, mut y) = (0, 0);
+        for op in &script {
+            match op {
+                Ops::Equal => {
+                    assert_eq!(left[x], right[y]);
+                    (x, y) = (x + 1, y + 1);
+                }
+                Ops::Delete => x += 1,
+                Ops::Insert => y += 1,
+            }
+        }
+        assert_eq!((x, y), (left.len(), right.len()));
+        let edits = script.iter().filter(|&&op| op != Ops::Equal).count();
+        let shortest = left.len() + right.len() - 2 * lcs_len(left, right);
+        assert_eq!(edits, shortest, "{left:?} -> {right:?}");
+    }
+
+    #[test]
+    fn edit_scripts_are_shortest() {
+        for (left, right) in [
+            ("", ""),
+            ("", "abc"),
+            ("abc", ""),
+            ("abc", "abc"),
+            ("abcabba", "cbabac"),
+            ("abgdef", "gh"),
+            ("xaxbxcx", "abc"),
+            ("abc", "xaxbxcx"),
+            ("aaaa", "aa"),
+            ("ab", "ba"),
+        ] {
+            assert_shortest(left.as_bytes(), right.as_bytes());
+        }
+        // Pseudo-random inputs over a small alphabet, to get many near misses
+        let mut state = 1u32;
+        let mut next = || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            state >> 16
+        };
+        for _ in 0..200 {
+            let (left_len, right_len) = (next() as usize % 30, next() as usize % 30);
+            let left: Vec<u8> = (0..left_len).map(|_| b"abc"[next() as usize % 3]).collect();
+            let right: Vec<u8> = (0..right_len)
+                .map(|_| b"abc"[next() as usize % 3])
+                .collect();
+            assert_shortest(&left, &right);
+        }
+    }
+
+    #[test]
+    fn both_algorithms_agree_on_a_line_change() {
+        let left = "fn main() {\n    let x = 1;\n    println!(\"{x}\");\n}\n";
+        let right = "fn main() {\n    let x = 2;\n    println!(\"{x}\");\n}\n";
+        let segments = |diff: DiffVec| {
+            diff.0
+                .iter()
+                .map(|segment| (segment.op(), segment.data().iter().collect::<String>()))
+                .collect::<Vec<_>>()
+        };
+        let myers = segments(line_diff(left, right));
+        assert_eq!(
+            myers,
+            [
+                (Ops::Equal, "fn main() {\n".to_owned()),
+                (Ops::Delete, "    let x = 1;\n".to_owned()),
+                (Ops::Insert, "    let x = 2;\n".to_owned()),
+                (Ops::Equal, "    println!(\"{x}\");\n}\n".to_owned()),
+            ]
+        );
+        let dmp = segments(compute_line_diff(left, right, DEFAULT_DIFF_TIMEOUT).unwrap());
+        assert_eq!(myers, dmp);
+    }
+}
@@ -0,0 +1,126 @@
+//! `ndiff` (Python `difflib` style) rendering of line diffs.
+
+use crate::{DiffVec, SIMILARITY_CUTOFF, STYLE_DELETED, STYLE_INSERTED, compute_diff, unified};
+use anyhow::Result;
+use diff_match_patch_rs::Ops;
+use std::fmt::Write;
+
+/// Guide lines marking where a pair of similar lines differ, or `None` when
+/// the lines are too dissimilar to be paired.
+fn guides(left: &str, right: &str) -> Result<Option<(String, String)>> {
+    let diff = compute_diff(left, right)?;
+    if diff.similarity() < SIMILARITY_CUTOFF {
+        return Ok(None);
+    }
+
+    let (mut left_guide, mut right_guide) = (String::new(), String::new());
+    let mut segments = diff.0.iter().peekable();
+    while let Some(segment) = segments.next() {
+        match segment.op() {
+            Ops::Equal => {
+                left_guide.extend(std::iter::repeat_n(' ', segment.size()));
+                right_guide.extend(std::iter::repeat_n(' ', segment.size()));
+            }
+            Ops::Delete => {
+                // A deletion directly followed by an insertion is a replacement
+                match segments.next_if(|next| next.op() == Ops::Insert) {
+                    Some(insert) => {
+                        left_guide.extend(std::iter::repeat_n('^', segment.size()));
+                        right_guide.extend(std::iter::repeat_n('^', insert.size()));
+                    }
+                    None => left_guide.extend(std::iter::repeat_n('-', segment.size())),
+                }
+            }
+            Ops::Insert => match segments.next_if(|next| next.op() == Ops::Delete) {
+                Some(delete) => {
+                    left_guide.extend(std::iter::repeat_n('^', delete.size()));
+                    right_guide.extend(std::iter::repeat_n('^', segment.size()));
+                }
+                None => right_guide.extend(std::iter::repeat_n('+', segment.size())),
+            },
+        }
+    }
+    Ok(Some((
+        left_guide.trim_end().to_owned(),
+        right_guide.trim_end().to_owned(),
+    )))
+}
+
+/// Renders a line diff with `  `, `- ` and `+ ` prefixes, adding `? ` guide
+/// lines under pairs of similar removed and added lines.
+pub fn render(diff: &DiffVec, color: bool) -> Result<String> {
+    let lines = unified::lines(diff);
+    let mut out = String::new();
+    let mut line = |prefix: &str, text: &str, style: anstyle::Style| {
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        if color {
+            let _ = writeln!(out, "{style}{prefix}{text}{style:#}");
+        } else {
+            let _ = writeln!(out, "{prefix}{text}");
+        }
+    };
+    let plain = anstyle::Style::new();
+
+    let mut index = 0;
+    while index < lines.len() {
+        if lines[index].0 == Ops::Equal {
+            line("  ", &lines[index].1, plain);
+            index += 1;
+            continue;
+        }
+        // Pair up the removed and added lines of each change block
+        let block_end = lines[index..]
+            .iter()
+            .position(|(op, _)| *op == Ops::Equal)
+            .map_or(lines.len(), |len| index + len);
+        let block = &lines[index..block_end];
+        let removed = block.iter().filter(|(op, _)| *op == Ops::Delete);
+        let added = block.iter().filter(|(op, _)| *op == Ops::Insert);
+        let (removed, added) = (removed.collect::<Vec<_>>(), added.collect::<Vec<_>>());
+
+        for pair in 0..removed.len().max(added.len()) {
+            match (removed.get(pair), added.get(pair)) {
+                (Some((_, left)), Some((_, right))) => {
+                    let (left_text, right_text) = (
+                        left.strip_suffix('\n').unwrap_or(left),
+                        right.strip_suffix('\n').unwrap_or(right),
+                    );
+                    line("- ", left, STYLE_DELETED.style());
+                    match guides(left_text, right_text)? {
+                        Some((left_guide, right_guide)) => {
+                            if !left_guide.is_empty() {
+                                line("? ", &left_guide, plain);
+                            }
+                            line("+ ", right, STYLE_INSERTED.style());
+                            if !right_guide.is_empty() {
+                                line("? ", &right_guide, plain);
+                            }
+                        }
+                        None => line("+ ", right, STYLE_INSERTED.style()),
+                    }
+                }
+                (Some((_, left)), None) => line("- ", left, STYLE_DELETED.style()),
+                (None, Some((_, right))) => line("+ ", right, STYLE_INSERTED.style()),
+                (None, None) => unreachable!(),
+            }
+        }
+        index = block_end;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_DIFF_TIMEOUT, compute_line_diff};
+
+    #[test]
+    fn guide_marks_a_single_changed_char() {
+        let diff =
+            compute_line_diff("same\nabcdef\n", "same\nabXdef\n", DEFAULT_DIFF_TIMEOUT).unwrap();
+        assert_eq!(
+            render(&diff, false).unwrap(),
+            "  same\n- abcdef\n?   ^\n+ abXdef\n?   ^\n"
+        );
+    }
+}
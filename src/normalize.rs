@@ -0,0 +1,240 @@
+//! Transformations applied to both inputs before they are compared.
+
+use crate::json_path::{self, JsonPath};
+use regex::Regex;
+use std::num::NonZeroUsize;
+
+/// The normalizations applied to both sides of a comparison.
+#[derive(Clone, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Normalization {
+    /// JSON inputs are replaced by the values at these paths, one per line
+    pub json_paths: Vec<JsonPath>,
+    /// Lines matching any of these are dropped entirely
+    pub ignore_regex: Vec<Regex>,
+    /// Lines starting with any of these after leading whitespace are dropped
+    pub ignore_comment: Vec<String>,
+    /// Matches of these are replaced by `mask_with`
+    pub mask: Vec<Regex>,
+    /// Replacement for masked text, which may refer to capture groups as `$1`
+    pub mask_with: String,
+    /// Rewrite every run of backslashes to a single `/`
+    pub normalize_paths: bool,
+    /// Strip leading and trailing whitespace from the whole text
+    pub trim: bool,
+    /// Replace every run of spaces and tabs with a single space
+    pub collapse_spaces: bool,
+    /// Lowercase the whole text
+    pub ignore_case: bool,
+    /// Expand tabs to spaces, with tab stops this many columns apart
+    pub tabsize: Option<NonZeroUsize>,
+}
+
+impl Normalization {
+    pub fn apply(&self, text: &str) -> String {
+        let selected = (!self.json_paths.is_empty())
+            .then(|| json_path::select(&self.json_paths, text))
+            .flatten();
+        let text = selected.as_deref().unwrap_or(text);
+        let mut text = if self.ignore_regex.is_empty() && self.ignore_comment.is_empty() {
+            text.to_owned()
+        } else {
+            text.split_inclusive('\n')
+                .filter(|line| {
+                    let content = line.strip_suffix('\n').unwrap_or(line);
+                    let code = content.trim_start();
+                    !self
+                        .ignore_regex
+                        .iter()
+                        .any(|regex| regex.is_match(content))
+                        && !self
+                            .ignore_comment
+                            .iter()
+                            .any(|prefix| code.starts_with(prefix.as_str()))
+                })
+                .collect()
+        };
+        for mask in &self.mask {
+            text = mask
+                .replace_all(&text, self.mask_with.as_str())
+                .into_owned();
+        }
+        if let Some(tabsize) = self.tabsize {
+            text = expand_tabs(&text, tabsize.get());
+        }
+        if self.collapse_spaces {
+            text = collapse_spaces(&text);
+        }
+        if self.normalize_paths {
+            text = unify_separators(&text);
+        }
+        if self.ignore_case {
+            text = text.to_lowercase();
+        }
+        if self.trim {
+            text = text.trim().to_owned();
+        }
+        text
+    }
+}
+
+/// Replaces each run of spaces and tabs with a single space, leaving line
+/// breaks alone.
+fn collapse_spaces(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut previous_blank = false;
+    for c in text.chars() {
+        let blank = c == ' ' || c == '\t';
+        if !(blank && previous_blank) {
+            out.push(if blank { ' ' } else { c });
+        }
+        previous_blank = blank;
+    }
+    out
+}
+
+/// Replaces each tab with the spaces up to the next tab stop, counting
+/// columns from the start of each line.
+fn expand_tabs(text: &str, tabsize: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = tabsize - column % tabsize;
+                out.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            '\n' => {
+                out.push(c);
+                column = 0;
+            }
+            _ => {
+                out.push(c);
+                column += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Replaces each run of backslashes with a single `/`, so `C:\\dir\file`
+/// and `C:/dir/file` compare equal.
+///
+/// Backslashes can't be told apart from path separators, so escapes such as
+/// a literal `\n` in the output are rewritten too.
+fn unify_separators(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut previous_backslash = false;
+    for c in text.chars() {
+        match c {
+            '\\' if previous_backslash => {}
+            '\\' => out.push('/'),
+            _ => out.push(c),
+        }
+        previous_backslash = c == '\\';
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignore_regex_drops_matching_lines() {
+        let normalization = Normalization {
+            ignore_regex: vec![Regex::new("^DEBUG").unwrap()],
+            ..Normalization::default()
+        };
+        let left = "start\nDEBUG took 5ms\nresult 42\n";
+        let right = "DEBUG pid 7\nstart\nresult 42\nDEBUG took 9ms";
+        assert_eq!(normalization.apply(left), "start\nresult 42\n");
+        assert_eq!(normalization.apply(left), normalization.apply(right));
+    }
+
+    #[test]
+    fn mask_replacement_can_keep_capture_groups() {
+        let normalization = Normalization {
+            mask: vec![Regex::new(r"(id=)\d+").unwrap()],
+            mask_with: "${1}<N>".to_owned(),
+            ..Normalization::default()
+        };
+        assert_eq!(
+            normalization.apply("user id=42, order id=7\n"),
+            "user id=<N>, order id=<N>\n"
+        );
+    }
+
+    #[test]
+    fn windows_paths_match_their_unix_form() {
+        let normalization = Normalization {
+            normalize_paths: true,
+            ..Normalization::default()
+        };
+        assert_eq!(
+            normalization.apply("wrote C:\\build\\\\out\\app.exe\n"),
+            normalization.apply("wrote C:/build/out/app.exe\n")
+        );
+        assert_eq!(unify_separators(r"a\\b\c"), "a/b/c");
+    }
+
+    #[test]
+    fn trim_ignores_surrounding_blank_lines() {
+        let normalization = Normalization {
+            trim: true,
+            ..Normalization::default()
+        };
+        assert_eq!(
+            normalization.apply("\n\n  result\n  value\n\n"),
+            normalization.apply("result\n  value")
+        );
+        assert_ne!(
+            normalization.apply("result\nvalue"),
+            normalization.apply("result\n\nvalue")
+        );
+    }
+
+    #[test]
+    fn collapse_spaces_ignores_column_padding() {
+        let normalization = Normalization {
+            collapse_spaces: true,
+            ..Normalization::default()
+        };
+        assert_eq!(
+            normalization.apply("name   size\t\tcount\nfoo    12\t3\n"),
+            normalization.apply("name size\tcount\nfoo 12      3\n")
+        );
+        assert_eq!(collapse_spaces("a \t b\n\n  c"), "a b\n\n c");
+    }
+
+    #[test]
+    fn comment_lines_are_dropped() {
+        let normalization = Normalization {
+            ignore_comment: vec!["#".to_owned(), "//".to_owned()],
+            ..Normalization::default()
+        };
+        let left = "# written by hand\nport = 80\n  # the default\nhost = a # local\n";
+        let right = "# generated\nport = 80\n// copied\nhost = a # local\n";
+        assert_eq!(normalization.apply(left), "port = 80\nhost = a # local\n");
+        assert_eq!(normalization.apply(left), normalization.apply(right));
+    }
+
+    #[test]
+    fn tabs_match_spaces_up_to_the_next_stop() {
+        let tabs = |size| Normalization {
+            tabsize: NonZeroUsize::new(size),
+            ..Normalization::default()
+        };
+        let (tabbed, spaced) = ("ab\tc\n\tx\n", "ab  c\n    x\n");
+        assert_eq!(tabs(4).apply(tabbed), spaced);
+        assert_ne!(tabs(8).apply(tabbed), spaced);
+        assert_eq!(tabs(8).apply(tabbed), "ab      c\n        x\n");
+
+        let collapsed = Normalization {
+            collapse_spaces: true,
+            ..tabs(4)
+        };
+        assert_eq!(collapsed.apply(tabbed), collapsed.apply("ab c\n x\n"));
+    }
+}
@@ -0,0 +1,101 @@
+//! Rendering of line diffs as one line per change region.
+
+use crate::{DiffVec, STYLE_DELETED, STYLE_INSERTED, unified};
+use diff_match_patch_rs::Ops;
+use std::fmt::Write;
+
+/// Quotes the lines of one side of a change, escaping newlines between them.
+fn quoted(prefix: char, lines: &[&str]) -> String {
+    let text = lines.concat();
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    format!("\"{prefix}{}\"", text.escape_debug())
+}
+
+/// Renders every run of changed lines as `L<line>: "-removed" "+added"`,
+/// numbered by its first line in the left input and cut to `width`
+/// characters.
+pub fn render(diff: &DiffVec, color: bool, width: usize) -> String {
+    let lines = unified::lines(diff);
+    let mut out = String::new();
+    let mut left_line = 1;
+    let mut index = 0;
+    while index < lines.len() {
+        if lines[index].0 == Ops::Equal {
+            left_line += 1;
+            index += 1;
+            continue;
+        }
+        let block_end = lines[index..]
+            .iter()
+            .position(|(op, _)| *op == Ops::Equal)
+            .map_or(lines.len(), |len| index + len);
+        let side = |side_op| {
+            lines[index..block_end]
+                .iter()
+                .filter(|(op, _)| *op == side_op)
+                .map(|(_, line)| line.as_str())
+                .collect::<Vec<_>>()
+        };
+        let (removed, added) = (side(Ops::Delete), side(Ops::Insert));
+
+        let plain = anstyle::Style::new();
+        let mut parts = vec![(plain, format!("L{left_line}:"))];
+        for (prefix, style, side) in [
+            ('-', STYLE_DELETED.style(), &removed),
+            ('+', STYLE_INSERTED.style(), &added),
+        ] {
+            if !side.is_empty() {
+                parts.extend([(plain, " ".to_owned()), (style, quoted(prefix, side))]);
+            }
+        }
+
+        // Cut the line, keeping the last column for the ellipsis
+        let total = parts
+            .iter()
+            .map(|(_, part)| part.chars().count())
+            .sum::<usize>();
+        let mut budget = if total > width {
+            width.saturating_sub(1)
+        } else {
+            total
+        };
+        for (style, part) in &parts {
+            let text = part.chars().take(budget).collect::<String>();
+            budget -= text.chars().count();
+            if color && !text.is_empty() {
+                let _ = write!(out, "{style}{text}{style:#}");
+            } else {
+                out.push_str(&text);
+            }
+        }
+        if total > width {
+            out.push('…');
+        }
+        out.push('\n');
+
+        left_line += removed.len();
+        index = block_end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_DIFF_TIMEOUT, compute_line_diff};
+
+    #[test]
+    fn each_change_is_one_numbered_line_cut_to_width() {
+        let left = "a\nold text\nb\nc\nd\ngone\n";
+        let right = "a\nnew text\nb\nadded\nc\nd\n";
+        let diff = compute_line_diff(left, right, DEFAULT_DIFF_TIMEOUT).unwrap();
+        assert_eq!(
+            render(&diff, false, 80),
+            "L2: \"-old text\" \"+new text\"\nL4: \"+added\"\nL6: \"-gone\"\n"
+        );
+        assert_eq!(
+            render(&diff, false, 12),
+            "L2: \"-old t…\nL4: \"+added\"\nL6: \"-gone\"\n"
+        );
+    }
+}
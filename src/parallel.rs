@@ -0,0 +1,69 @@
+//! Mapping over items on several worker threads.
+
+use std::sync::{
+    Mutex, PoisonError,
+    atomic::{AtomicUsize, Ordering},
+};
+
+/// Applies `f` to every item on `jobs` worker threads, returning the results
+/// in the order of `items`.
+pub fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(items.iter().map(|_| None).collect::<Vec<_>>());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    let result = f(item);
+                    results.lock().unwrap_or_else(PoisonError::into_inner)[index] = Some(result);
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner)
+        .into_iter()
+        .map(|result| result.expect("every item is processed by a worker"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn results_follow_the_order_of_the_items() {
+        let items = (0..20).collect::<Vec<u64>>();
+        let results = parallel_map(&items, 4, |&item| {
+            thread::sleep(Duration::from_millis(20 - item));
+            item * 2
+        });
+        assert_eq!(results, (0..20).map(|item| item * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn one_job_runs_every_item_on_a_single_thread() {
+        let items = [1, 2, 3];
+        let results = parallel_map(&items, 1, |&item| (item, thread::current().id()));
+        assert_eq!(
+            results.iter().map(|(item, _)| *item).collect::<Vec<_>>(),
+            items
+        );
+        assert!(results.iter().all(|(_, id)| *id == results[0].1));
+    }
+
+    #[test]
+    fn no_items_give_no_results() {
+        assert!(parallel_map(&[] as &[u8], 0, |&item| item).is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+//! Ordered printing of output blocks that are produced out of order.
+
+use std::{collections::BTreeMap, io::Write};
+
+/// Buffers blocks keyed by their index and writes them in index order as
+/// soon as every earlier block has been written.
+pub struct OrderedPrinter<W> {
+    out: W,
+    next: usize,
+    pending: BTreeMap<usize, String>,
+}
+
+impl<W: Write> OrderedPrinter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// The writer, for output that bypasses the ordering.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    /// The writer, once every block has been pushed.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Queues the block at `index`, then writes the contiguous run of queued
+    /// blocks that follows the last written one.
+    pub fn push(&mut self, index: usize, block: String) -> std::io::Result<()> {
+        self.pending.insert(index, block);
+        while let Some(block) = self.pending.remove(&self.next) {
+            self.out.write_all(block.as_bytes())?;
+            self.next += 1;
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// A writer whose output can be read while a printer owns it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn blocks_are_held_back_until_earlier_ones_arrive() {
+        let out = Shared::default();
+        let mut printer = OrderedPrinter::new(out.clone());
+        printer.push(2, "c".to_owned()).unwrap();
+        printer.push(1, "b".to_owned()).unwrap();
+        assert_eq!(out.text(), "");
+        printer.push(0, "a".to_owned()).unwrap();
+        assert_eq!(out.text(), "abc");
+        printer.push(4, "e".to_owned()).unwrap();
+        assert_eq!(out.text(), "abc");
+        printer.push(3, "d".to_owned()).unwrap();
+        assert_eq!(out.text(), "abcde");
+    }
+
+    #[test]
+    fn blocks_in_order_are_written_right_away() {
+        let out = Shared::default();
+        let mut printer = OrderedPrinter::new(out.clone());
+        for (index, block) in ["a", "b", "c"].into_iter().enumerate() {
+            printer.push(index, block.to_owned()).unwrap();
+            assert_eq!(out.text(), "abc"[..=index]);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! The `--progress` line of a run on stderr, with an estimate of the time
+//! left.
+
+use std::{collections::VecDeque, io::Write, time::Duration};
+
+/// Number of most recently finished cases whose runtimes are averaged.
+const AVERAGED_CASES: usize = 20;
+
+pub struct Progress {
+    total: usize,
+    done: usize,
+    /// Number of cases run in parallel
+    jobs: usize,
+    /// Runtimes of the last `AVERAGED_CASES` finished cases, oldest first
+    recent: VecDeque<Duration>,
+}
+
+impl Progress {
+    pub fn new(total: usize, jobs: usize) -> Self {
+        Self {
+            total,
+            done: 0,
+            jobs: jobs.max(1),
+            recent: VecDeque::with_capacity(AVERAGED_CASES),
+        }
+    }
+
+    pub fn finish(&mut self, duration: Duration) {
+        self.done += 1;
+        if self.recent.len() == AVERAGED_CASES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(duration);
+    }
+
+    /// How long the remaining cases should take, given the mean runtime of
+    /// the recently finished ones and that `jobs` of them run at a time.
+    pub fn remaining(&self) -> Option<Duration> {
+        let count = u32::try_from(self.recent.len())
+            .ok()
+            .filter(|&count| count > 0)?;
+        let mean = self.recent.iter().sum::<Duration>() / count;
+        let left = self.total.saturating_sub(self.done);
+        let rounds = u32::try_from(left.div_ceil(self.jobs)).unwrap_or(u32::MAX);
+        Some(mean.saturating_mul(rounds))
+    }
+
+    /// The progress line, like `running 30/200 (~12s left)`.
+    fn line(&self) -> String {
+        let line = format!("running {}/{}", self.done, self.total);
+        match self.remaining() {
+            Some(remaining) => {
+                // Rounded to whole seconds, which is all the estimate is good for
+                let seconds = (remaining + Duration::from_millis(500)).as_secs();
+                let seconds = Duration::from_secs(seconds);
+                format!("{line} (~{} left)", humantime::format_duration(seconds))
+            }
+            None => line,
+        }
+    }
+
+    /// Replaces the line on the terminal with the current one.
+    pub fn draw(&self) {
+        eprint!("\r\x1b[K{}", self.line());
+        let _ = std::io::stderr().flush();
+    }
+
+    /// Erases the line, e.g. before printing other output.
+    pub fn clear() {
+        eprint!("\r\x1b[K");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_averages_recent_runtimes() {
+        let mut progress = Progress::new(200, 1);
+        assert_eq!(progress.remaining(), None);
+        assert_eq!(progress.line(), "running 0/200");
+        for millis in [300, 500] {
+            progress.finish(Duration::from_millis(millis));
+        }
+        assert_eq!(progress.remaining(), Some(Duration::from_millis(400 * 198)));
+        assert_eq!(progress.line(), "running 2/200 (~1m 19s left)");
+
+        // Only the last cases count, so a change of pace shows up quickly
+        for _ in 0..AVERAGED_CASES {
+            progress.finish(Duration::from_millis(100));
+        }
+        assert_eq!(progress.remaining(), Some(Duration::from_millis(100 * 178)));
+
+        let mut parallel = Progress::new(10, 4);
+        parallel.finish(Duration::from_secs(2));
+        assert_eq!(parallel.line(), "running 1/10 (~6s left)", "3 rounds of 4");
+    }
+}
@@ -0,0 +1,71 @@
+//! Comparison of single-line `key=value;key2=value2` records by key, for
+//! `--columns`.
+
+use crate::{STYLE_DELETED, STYLE_INSERTED};
+use std::collections::BTreeMap;
+
+/// The pairs of a record, keyed by their trimmed keys. A field without
+/// `pair_sep` is a key with an empty value.
+fn parse<'a>(record: &'a str, field_sep: &str, pair_sep: &str) -> BTreeMap<&'a str, &'a str> {
+    record
+        .trim_end_matches(['\r', '\n'])
+        .split(field_sep)
+        .filter(|field| !field.trim().is_empty())
+        .map(|field| {
+            let (key, value) = field.split_once(pair_sep).unwrap_or((field, ""));
+            (key.trim(), value.trim())
+        })
+        .collect()
+}
+
+/// One line per key whose value differs between the records, in key order:
+/// `key changed: old -> new`, `key added: new` or `key removed: old`.
+pub fn compare(left: &str, right: &str, field_sep: &str, pair_sep: &str) -> Vec<String> {
+    let (left, right) = (
+        parse(left, field_sep, pair_sep),
+        parse(right, field_sep, pair_sep),
+    );
+    let mut keys = left.keys().chain(right.keys()).collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.dedup();
+    keys.into_iter()
+        .filter_map(|key| match (left.get(key), right.get(key)) {
+            (Some(old), Some(new)) if old == new => None,
+            (Some(old), Some(new)) => Some(format!(
+                "{key} changed: {STYLE_DELETED}{old}{STYLE_DELETED:#} -> {STYLE_INSERTED}{new}{STYLE_INSERTED:#}"
+            )),
+            (None, Some(new)) => Some(format!(
+                "{key} added: {STYLE_INSERTED}{new}{STYLE_INSERTED:#}"
+            )),
+            (Some(old), None) => Some(format!(
+                "{key} removed: {STYLE_DELETED}{old}{STYLE_DELETED:#}"
+            )),
+            (None, None) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_and_added_keys_are_listed() {
+        assert_eq!(
+            compare("a=1;b=2", "a=1;b=3;c=4", ";", "="),
+            [
+                format!(
+                    "b changed: {STYLE_DELETED}2{STYLE_DELETED:#} -> {STYLE_INSERTED}3{STYLE_INSERTED:#}"
+                ),
+                format!("c added: {STYLE_INSERTED}4{STYLE_INSERTED:#}"),
+            ]
+        );
+        assert_eq!(
+            compare("PATH: /bin, HOME: /root\n", "HOME: /root", ",", ":"),
+            [format!(
+                "PATH removed: {STYLE_DELETED}/bin{STYLE_DELETED:#}"
+            )]
+        );
+        assert!(compare("a=1; b", "b=;a=1", ";", "=").is_empty());
+    }
+}
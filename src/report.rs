@@ -0,0 +1,89 @@
+//! Per-case results of a run, written with `--report` to a `.json` file.
+
+use crate::{Outcome, error::DiffError};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    time::Duration,
+};
+
+#[derive(Deserialize, Serialize)]
+pub struct CaseResult {
+    pub note: String,
+    pub outcome: Outcome,
+    pub duration_ms: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Report {
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    pub fn new<'a>(cases: impl Iterator<Item = (&'a str, Outcome, Duration)>) -> Self {
+        Self {
+            cases: cases
+                .map(|(note, outcome, duration)| CaseResult {
+                    note: note.to_owned(),
+                    outcome,
+                    duration_ms: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DiffError> {
+        let text = std::fs::read_to_string(path).map_err(DiffError::io(format!(
+            "Failed to read report: {}",
+            path.display()
+        )))?;
+        serde_json::from_str(&text).map_err(DiffError::parse(format!(
+            "Failed to parse report: {}",
+            path.display()
+        )))
+    }
+
+    /// Notes of the cases that failed the run or were left unrun by its
+    /// timeout.
+    pub fn failures(&self) -> BTreeSet<String> {
+        self.cases
+            .iter()
+            .filter(|case| case.outcome.is_failure() || case.outcome == Outcome::NotRun)
+            .map(|case| case.note.clone())
+            .collect()
+    }
+
+    /// The outcome of every case by note.
+    pub fn outcomes(&self) -> BTreeMap<String, Outcome> {
+        self.cases
+            .iter()
+            .map(|case| (case.note.clone(), case.outcome))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_include_cases_left_unrun() {
+        let report = Report::new(
+            [
+                ("passed", Outcome::Passed),
+                ("failed", Outcome::Failed),
+                ("xpass", Outcome::UnexpectedPass),
+                ("xfail", Outcome::ExpectedFailure),
+                ("timed out", Outcome::NotRun),
+                ("skipped", Outcome::Skipped),
+            ]
+            .into_iter()
+            .map(|(note, outcome)| (note, outcome, Duration::ZERO)),
+        );
+        assert_eq!(
+            report.failures().into_iter().collect::<Vec<_>>(),
+            ["failed", "timed out", "xpass"]
+        );
+    }
+}
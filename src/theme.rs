@@ -0,0 +1,114 @@
+//! Color themes, picked once at startup with `--theme`.
+
+use anstyle::{AnsiColor, Color, Effects, Style};
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum Theme {
+    /// Red and cyan backgrounds for changes, green for passed cases
+    #[default]
+    Default,
+    /// Orange and blue backgrounds, with `-` and `+` before inline changes
+    Colorblind,
+    /// No colors: struck through, underlined and bold text
+    Mono,
+}
+
+/// What a styled piece of output stands for.
+#[derive(Clone, Copy)]
+pub enum Role {
+    /// Deleted text, failed cases and other problems
+    Deleted,
+    /// Inserted text and skipped cases
+    Inserted,
+    Passed,
+    /// Details that are there to be copied rather than read, such as
+    /// reproduction commands
+    Hint,
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Sets the theme of all later output; only the first call has an effect.
+pub fn set(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+pub fn current() -> Theme {
+    THEME.get().copied().unwrap_or_default()
+}
+
+const ORANGE: Color = Color::Ansi256(anstyle::Ansi256Color(208));
+
+impl Theme {
+    fn style(self, role: Role) -> Style {
+        let background = |color: Color| Style::new().bg_color(Some(color));
+        match (self, role) {
+            (Self::Default, Role::Deleted) => background(AnsiColor::Red.into()),
+            (Self::Default, Role::Inserted) => background(AnsiColor::Cyan.into()),
+            (Self::Default, Role::Passed) => background(AnsiColor::Green.into()),
+            (Self::Colorblind, Role::Deleted) => background(ORANGE),
+            (Self::Colorblind, Role::Inserted | Role::Passed) => background(AnsiColor::Blue.into()),
+            (Self::Mono, Role::Deleted) => Style::new().effects(Effects::STRIKETHROUGH),
+            (Self::Mono, Role::Inserted) => Style::new().effects(Effects::UNDERLINE),
+            (Self::Mono, Role::Passed) => Style::new().effects(Effects::BOLD),
+            (_, Role::Hint) => Style::new().effects(Effects::DIMMED),
+        }
+    }
+
+    /// Whether inline changes are marked with `-` and `+` besides their style.
+    pub fn markers(self) -> bool {
+        matches!(self, Self::Colorblind)
+    }
+}
+
+impl Role {
+    /// The style of the role in the current theme.
+    pub fn style(self) -> Style {
+        current().style(self)
+    }
+}
+
+/// Writes the escape codes that start the style, or with `{:#}` reset it,
+/// like `Style` itself.
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let style = self.style();
+        if f.alternate() {
+            write!(f, "{style:#}")
+        } else {
+            write!(f, "{style}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The escape codes starting the style of each role, in `Role` order.
+    fn codes(theme: Theme) -> Vec<String> {
+        [Role::Deleted, Role::Inserted, Role::Passed, Role::Hint]
+            .map(|role| theme.style(role).to_string())
+            .to_vec()
+    }
+
+    #[test]
+    fn each_theme_has_its_codes_and_markers() {
+        assert_eq!(
+            codes(Theme::Default),
+            ["\x1b[41m", "\x1b[46m", "\x1b[42m", "\x1b[2m"]
+        );
+        assert_eq!(
+            codes(Theme::Colorblind),
+            ["\x1b[48;5;208m", "\x1b[44m", "\x1b[44m", "\x1b[2m"]
+        );
+        assert_eq!(
+            codes(Theme::Mono),
+            ["\x1b[9m", "\x1b[4m", "\x1b[1m", "\x1b[2m"]
+        );
+        assert!(Theme::Colorblind.markers());
+        assert!(!Theme::Default.markers() && !Theme::Mono.markers());
+    }
+}
@@ -0,0 +1,199 @@
+//! Unified (`diff -u` style) rendering of line diffs.
+
+use crate::{DiffVec, STYLE_DELETED, STYLE_INSERTED};
+use diff_match_patch_rs::Ops;
+use regex::Regex;
+use std::fmt::Write;
+
+/// Number of unchanged lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+/// Splits the segments of a line diff into single lines.
+pub(crate) fn lines(diff: &DiffVec) -> Vec<(Ops, String)> {
+    diff.0
+        .iter()
+        .flat_map(|diff| {
+            let text = diff.data().iter().collect::<String>();
+            text.split_inclusive('\n')
+                .map(|line| (diff.op(), line.to_owned()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Formats a hunk range, where an empty range starts at the line before it.
+fn range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{count}", start + 1),
+    }
+}
+
+/// The last line of the left input before line `end` of the diff that
+/// matches `section`, without its line break.
+fn section_before<'a>(lines: &'a [(Ops, String)], end: usize, section: &Regex) -> Option<&'a str> {
+    lines[..end]
+        .iter()
+        .rev()
+        .filter(|(op, _)| *op != Ops::Insert)
+        .map(|(_, line)| line.trim_end_matches(['\n', '\r']))
+        .find(|line| section.is_match(line))
+}
+
+/// Renders a line diff as unified hunks with `CONTEXT_LINES` lines of
+/// context, optionally coloring removed and added lines. Given `section`,
+/// each hunk header ends with the closest line above the hunk matching it,
+/// like the function names in `git diff`.
+pub fn render(diff: &DiffVec, color: bool, section: Option<&Regex>) -> String {
+    let lines = lines(diff);
+
+    // Line numbers on each side before every diff line
+    let mut positions = Vec::with_capacity(lines.len() + 1);
+    let (mut left, mut right) = (0, 0);
+    for &(op, _) in &lines {
+        positions.push((left, right));
+        left += usize::from(op != Ops::Insert);
+        right += usize::from(op != Ops::Delete);
+    }
+    positions.push((left, right));
+
+    let changes = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Ops::Equal)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &index in &changes {
+        match hunks.last_mut() {
+            Some((_, end)) if index - *end <= 2 * CONTEXT_LINES => *end = index,
+            _ => hunks.push((index, index)),
+        }
+    }
+
+    let mut out = String::new();
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT_LINES);
+        let end = (last + CONTEXT_LINES + 1).min(lines.len());
+        let (left_start, right_start) = positions[start];
+        let (left_end, right_end) = positions[end];
+        let _ = write!(
+            out,
+            "@@ -{} +{} @@",
+            range(left_start, left_end - left_start),
+            range(right_start, right_end - right_start)
+        );
+        match section.and_then(|section| section_before(&lines, start, section)) {
+            Some(heading) => {
+                let _ = writeln!(out, " {heading}");
+            }
+            None => out.push('\n'),
+        }
+
+        for (op, line) in &lines[start..end] {
+            let (prefix, style) = match op {
+                Ops::Equal => (' ', anstyle::Style::new()),
+                Ops::Delete => ('-', STYLE_DELETED.style()),
+                Ops::Insert => ('+', STYLE_INSERTED.style()),
+            };
+            let text = line.strip_suffix('\n');
+            let content = text.unwrap_or(line);
+            if color {
+                let _ = writeln!(out, "{style}{prefix}{content}{style:#}");
+            } else {
+                let _ = writeln!(out, "{prefix}{content}");
+            }
+            if text.is_none() {
+                let _ = writeln!(out, "{NO_NEWLINE_MARKER}");
+            }
+        }
+    }
+    out
+}
+
+/// Renders a line diff as a patch that `patch -p1` and `git apply` accept,
+/// with the paths made relative and prefixed by `a/` and `b/`, or nothing for
+/// equal inputs.
+pub fn patch(diff: &DiffVec, left_path: &str, right_path: &str) -> String {
+    let hunks = render(diff, false, None);
+    if hunks.is_empty() {
+        return hunks;
+    }
+    let path = |path: &str| {
+        path.trim_start_matches("./")
+            .trim_start_matches('/')
+            .replace('\\', "/")
+    };
+    format!(
+        "--- a/{}\n+++ b/{}\n{hunks}",
+        path(left_path),
+        path(right_path)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_DIFF_TIMEOUT, compute_line_diff};
+
+    fn unified(left: &str, right: &str, section: Option<&Regex>) -> String {
+        render(
+            &compute_line_diff(left, right, DEFAULT_DIFF_TIMEOUT).unwrap(),
+            false,
+            section,
+        )
+    }
+
+    #[test]
+    fn missing_final_newline_is_marked_after_the_line() {
+        assert_eq!(
+            unified("a\nb\n", "a\nc", None),
+            "@@ -1,2 +1,2 @@\n a\n-b\n+c\n\\ No newline at end of file\n"
+        );
+        assert!(!unified("a\nb\n", "a\nc\n", None).contains(NO_NEWLINE_MARKER));
+    }
+
+    #[test]
+    fn hunk_header_names_the_enclosing_section() {
+        let body = "    let a = 1;\n".repeat(6);
+        let left = format!("fn foo() {{\n{body}    bar(1);\n}}\n");
+        let right = format!("fn foo() {{\n{body}    bar(2);\n}}\n");
+        let section = Regex::new("^fn ").unwrap();
+        let header = unified(&left, &right, Some(&section));
+        assert_eq!(header.lines().next(), Some("@@ -5,5 +5,5 @@ fn foo() {"));
+        let plain = unified(&left, &right, None);
+        assert_eq!(plain.lines().next(), Some("@@ -5,5 +5,5 @@"));
+    }
+
+    #[test]
+    fn patch_applies_with_the_patch_tool() {
+        let tool = std::process::Command::new("patch")
+            .arg("--version")
+            .output();
+        if !tool.is_ok_and(|output| output.status.success()) {
+            return;
+        }
+        let left = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+        let right = "one\n2\nthree\nfour\nfive\nsix\nseven\neight\nnine";
+        let diff = compute_line_diff(left, right, DEFAULT_DIFF_TIMEOUT).unwrap();
+        let patch = patch(&diff, "./notes.txt", "./notes.txt");
+        assert!(patch.starts_with("--- a/notes.txt\n+++ b/notes.txt\n@@ "));
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, left).unwrap();
+        let mut child = std::process::Command::new("patch")
+            .args(["-p1", "--quiet"])
+            .current_dir(dir.path())
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(&mut child.stdin.take().unwrap(), patch.as_bytes()).unwrap();
+        assert!(child.wait().unwrap().success());
+        assert_eq!(std::fs::read_to_string(file).unwrap(), right);
+    }
+}